@@ -1,11 +1,16 @@
 use cosmwasm_std::{
-    attr, Addr, Api, Binary, BlockInfo, Deps, DepsMut, Env, MessageInfo, Response, StdError,
-    StdResult, Storage, Uint128,
+    attr, to_vec, Addr, Api, Binary, BlockInfo, CanonicalAddr, Deps, DepsMut, Env, MessageInfo,
+    Response, StdError, StdResult, Storage, Uint128,
 };
 use cw20::{AllowanceResponse, Cw20ReceiveMsg, Expiration};
+use cw_storage_plus::U64Key;
+use ripemd160::Ripemd160;
+use sha2::{Digest, Sha256};
 
+use crate::contract::{batch_amount, move_batch};
 use crate::error::ContractError;
-use crate::state::{ALLOWANCES, BALANCES, TOKEN_INFO};
+use crate::msg::{PermitMessage, PermitNonceResponse};
+use crate::state::{ALLOWANCES, BALANCES, PERMIT_NONCES, TOKEN_INFO, TOTAL_SUPPLY_HISTORY};
 
 pub fn execute_increase_allowance(
     deps: DepsMut,
@@ -20,16 +25,113 @@ pub fn execute_increase_allowance(
         return Err(ContractError::CannotSetOwnAccount {});
     }
 
-    ALLOWANCES.update(
+    increase_allowance(
         deps.storage,
-        (
-            deps.api
-                .addr_canonicalize(&info.sender.to_string())?
-                .as_slice(),
-            deps.api
-                .addr_canonicalize(&spender_addr.to_string())?
-                .as_slice(),
-        ),
+        &deps.api.addr_canonicalize(info.sender.as_str())?,
+        &deps.api.addr_canonicalize(spender_addr.as_str())?,
+        amount,
+        expires,
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "increase_allowance"),
+        attr("owner", info.sender),
+        attr("spender", spender),
+        attr("amount", amount),
+    ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn execute_permit(
+    deps: DepsMut,
+    env: Env,
+    owner_pubkey: Binary,
+    spender: String,
+    amount: Uint128,
+    expires: Option<Expiration>,
+    deadline: Expiration,
+    nonce: u64,
+    signature: Binary,
+) -> Result<Response, ContractError> {
+    if deadline.is_expired(&env.block) {
+        return Err(ContractError::PermitExpired {});
+    }
+
+    let owner_raw = pubkey_to_canonical(&owner_pubkey)?;
+    let owner_addr = deps.api.addr_humanize(&owner_raw)?;
+    let spender_addr = deps.api.addr_validate(&spender)?;
+    if spender_addr == owner_addr {
+        return Err(ContractError::CannotSetOwnAccount {});
+    }
+
+    let expected = PERMIT_NONCES
+        .may_load(deps.storage, owner_raw.as_slice())?
+        .unwrap_or_default();
+    if nonce != expected {
+        return Err(ContractError::InvalidNonce { expected });
+    }
+
+    let message = PermitMessage {
+        chain_id: env.block.chain_id,
+        contract: env.contract.address.to_string(),
+        spender: spender.clone(),
+        amount,
+        expires,
+        deadline,
+        nonce,
+    };
+    let message_hash = Sha256::digest(&to_vec(&message)?);
+    if !deps
+        .api
+        .secp256k1_verify(&message_hash, &signature, &owner_pubkey)
+        .unwrap_or(false)
+    {
+        return Err(ContractError::InvalidSignature {});
+    }
+    PERMIT_NONCES.save(deps.storage, owner_raw.as_slice(), &(nonce + 1))?;
+
+    increase_allowance(
+        deps.storage,
+        &owner_raw,
+        &deps.api.addr_canonicalize(spender_addr.as_str())?,
+        amount,
+        expires,
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "permit"),
+        attr("owner", owner_addr),
+        attr("spender", spender),
+        attr("amount", amount),
+        attr("nonce", nonce.to_string()),
+    ]))
+}
+
+pub fn execute_increase_nonce(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let owner_raw = deps.api.addr_canonicalize(info.sender.as_str())?;
+    let nonce = PERMIT_NONCES.update(
+        deps.storage,
+        owner_raw.as_slice(),
+        |nonce| -> StdResult<_> { Ok(nonce.unwrap_or_default() + 1) },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "increase_nonce"),
+        attr("owner", info.sender),
+        attr("nonce", nonce.to_string()),
+    ]))
+}
+
+fn increase_allowance(
+    storage: &mut dyn Storage,
+    owner: &CanonicalAddr,
+    spender: &CanonicalAddr,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> StdResult<AllowanceResponse> {
+    ALLOWANCES.update(
+        storage,
+        (owner.as_slice(), spender.as_slice()),
         |allow| -> StdResult<_> {
             let mut val = allow.unwrap_or_default();
             if let Some(exp) = expires {
@@ -38,14 +140,17 @@ pub fn execute_increase_allowance(
             val.allowance += amount;
             Ok(val)
         },
-    )?;
+    )
+}
 
-    Ok(Response::new().add_attributes(vec![
-        attr("action", "increase_allowance"),
-        attr("owner", info.sender),
-        attr("spender", spender),
-        attr("amount", amount),
-    ]))
+/// Derives the account address of a compressed secp256k1 public key,
+/// which is `ripemd160(sha256(pubkey))` like for the Cosmos SDK accounts
+fn pubkey_to_canonical(pubkey: &[u8]) -> Result<CanonicalAddr, ContractError> {
+    if pubkey.len() != 33 {
+        return Err(ContractError::InvalidPubkey {});
+    }
+    let hash = Ripemd160::digest(&Sha256::digest(pubkey));
+    Ok(CanonicalAddr::from(hash.as_slice()))
 }
 
 pub fn execute_decrease_allowance(
@@ -150,6 +255,7 @@ pub fn execute_transfer_from(
         deps.api
             .addr_canonicalize(&owner_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> {
             Ok(balance.unwrap_or_default().checked_sub(amount)?)
         },
@@ -159,6 +265,7 @@ pub fn execute_transfer_from(
         deps.api
             .addr_canonicalize(&rcpt_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
     )?;
 
@@ -171,6 +278,48 @@ pub fn execute_transfer_from(
     ]))
 }
 
+pub fn execute_batch_transfer_from(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    owner: String,
+    transfers: Vec<(String, Uint128)>,
+) -> Result<Response, ContractError> {
+    let owner_addr = deps.api.addr_validate(&owner)?;
+    let total = batch_amount(&transfers)?;
+
+    // deduct allowance before doing anything else have enough allowance
+    deduct_allowance(
+        deps.storage,
+        deps.api,
+        &owner_addr,
+        &info.sender,
+        &env.block,
+        total,
+    )?;
+
+    let events = move_batch(
+        deps.storage,
+        deps.api,
+        env.block.height,
+        &owner_addr,
+        total,
+        transfers,
+    )?
+    .into_iter()
+    .map(|event| event.add_attribute("by", info.sender.clone()))
+    .collect::<Vec<_>>();
+
+    Ok(Response::new()
+        .add_attributes(vec![
+            attr("action", "batch_transfer_from"),
+            attr("from", owner),
+            attr("by", info.sender),
+            attr("amount", total),
+        ])
+        .add_events(events))
+}
+
 pub fn execute_burn_from(
     deps: DepsMut,
 
@@ -197,15 +346,21 @@ pub fn execute_burn_from(
         deps.api
             .addr_canonicalize(&owner_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> {
             Ok(balance.unwrap_or_default().checked_sub(amount)?)
         },
     )?;
     // reduce total_supply
-    TOKEN_INFO.update(deps.storage, |mut meta| -> StdResult<_> {
+    let meta = TOKEN_INFO.update(deps.storage, |mut meta| -> StdResult<_> {
         meta.total_supply = meta.total_supply.checked_sub(amount)?;
         Ok(meta)
     })?;
+    TOTAL_SUPPLY_HISTORY.save(
+        deps.storage,
+        U64Key::new(env.block.height),
+        &meta.total_supply,
+    )?;
 
     Ok(Response::new().add_attributes(vec![
         attr("action", "burn_from"),
@@ -243,6 +398,7 @@ pub fn execute_send_from(
         deps.api
             .addr_canonicalize(&owner_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> {
             Ok(balance.unwrap_or_default().checked_sub(amount)?)
         },
@@ -252,6 +408,7 @@ pub fn execute_send_from(
         deps.api
             .addr_canonicalize(&rcpt_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
     )?;
 
@@ -291,13 +448,29 @@ pub fn query_allowance(deps: Deps, owner: String, spender: String) -> StdResult<
     Ok(allowance)
 }
 
+pub fn query_permit_nonce(deps: Deps, owner: String) -> StdResult<PermitNonceResponse> {
+    let owner_raw = deps.api.addr_canonicalize(&owner)?;
+    let nonce = PERMIT_NONCES
+        .may_load(deps.storage, owner_raw.as_slice())?
+        .unwrap_or_default();
+    Ok(PermitNonceResponse { nonce })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, CosmosMsg, SubMsg, Timestamp, WasmMsg};
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        coins, CosmosMsg, Empty, Event, OwnedDeps, RecoverPubkeyError, SubMsg, Timestamp,
+        VerificationError, WasmMsg,
+    };
     use cw20::{Cw20Coin, TokenInfoResponse};
+    use k256::ecdsa::signature::Signer;
+    use k256::ecdsa::{Signature, SigningKey};
+    use k256::elliptic_curve::sec1::ToEncodedPoint;
 
     use crate::contract::{execute, instantiate, query_balance, query_token_info};
     use crate::msg::{ExecuteMsg, InstantiateMsg};
@@ -321,6 +494,7 @@ mod tests {
                 amount,
             }],
             mint: None,
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
@@ -614,6 +788,82 @@ mod tests {
         assert_eq!(err, ContractError::Expired {});
     }
 
+    #[test]
+    fn batch_transfer_from_respects_limits() {
+        let mut deps = mock_dependencies(&[]);
+        let owner = String::from("addr0001");
+        let spender = String::from("addr0002");
+        let rcpt1 = String::from("addr0003");
+        let rcpt2 = String::from("addr0004");
+
+        let start = Uint128::new(999999);
+        do_instantiate(deps.as_mut(), &owner, start);
+
+        // provide an allowance
+        let allow1 = Uint128::new(77777);
+        let msg = ExecuteMsg::IncreaseAllowance {
+            spender: spender.clone(),
+            amount: allow1,
+            expires: None,
+        };
+        let info = mock_info(owner.as_ref(), &[]);
+        execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        // cannot exceed the allowance with the sum of the batch
+        let msg = ExecuteMsg::BatchTransferFrom {
+            owner: owner.clone(),
+            transfers: vec![
+                (rcpt1.clone(), Uint128::new(50000)),
+                (rcpt2.clone(), Uint128::new(30000)),
+            ],
+        };
+        let info = mock_info(spender.as_ref(), &[]);
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::Overflow { .. })));
+
+        // valid batch of part of the allowance
+        let msg = ExecuteMsg::BatchTransferFrom {
+            owner: owner.clone(),
+            transfers: vec![
+                (rcpt1.clone(), Uint128::new(40000)),
+                (rcpt2.clone(), Uint128::new(30000)),
+            ],
+        };
+        let info = mock_info(spender.as_ref(), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "batch_transfer_from"),
+                attr("from", owner.clone()),
+                attr("by", spender.clone()),
+                attr("amount", Uint128::new(70000)),
+            ]
+        );
+        assert_eq!(res.events.len(), 2);
+        assert_eq!(
+            res.events[0],
+            Event::new("transfer").add_attributes(vec![
+                attr("from", owner.clone()),
+                attr("to", rcpt1.clone()),
+                attr("amount", Uint128::new(40000)),
+                attr("by", spender.clone()),
+            ])
+        );
+
+        // make sure money arrived
+        assert_eq!(
+            get_balance(deps.as_ref(), owner.clone()),
+            Uint128::new(929999)
+        );
+        assert_eq!(get_balance(deps.as_ref(), rcpt1), Uint128::new(40000));
+        assert_eq!(get_balance(deps.as_ref(), rcpt2), Uint128::new(30000));
+
+        // ensure it looks good
+        let allowance = query_allowance(deps.as_ref(), owner, spender).unwrap();
+        assert_eq!(allowance.allowance, Uint128::new(7777));
+    }
+
     #[test]
     fn burn_from_respects_limits() {
         let mut deps = mock_dependencies(&[]);
@@ -792,4 +1042,254 @@ mod tests {
         let err = execute(deps.as_mut(), env, info, msg).unwrap_err();
         assert_eq!(err, ContractError::Expired {});
     }
+
+    /// MockApi which also accepts the 20 byte addresses derived from public keys,
+    /// rendered as "pubkey" followed by their hex encoding
+    #[derive(Copy, Clone, Default)]
+    struct PubkeyApi(MockApi);
+
+    impl Api for PubkeyApi {
+        fn addr_validate(&self, human: &str) -> StdResult<Addr> {
+            self.addr_canonicalize(human)?;
+            Ok(Addr::unchecked(human))
+        }
+
+        fn addr_canonicalize(&self, human: &str) -> StdResult<CanonicalAddr> {
+            match human.strip_prefix("pubkey") {
+                Some(hex) => (0..hex.len())
+                    .step_by(2)
+                    .map(|i| {
+                        u8::from_str_radix(&hex[i..i + 2], 16)
+                            .map_err(|e| StdError::generic_err(e.to_string()))
+                    })
+                    .collect::<StdResult<Vec<u8>>>()
+                    .map(CanonicalAddr::from),
+                None => self.0.addr_canonicalize(human),
+            }
+        }
+
+        fn addr_humanize(&self, canonical: &CanonicalAddr) -> StdResult<Addr> {
+            if canonical.len() == 20 {
+                let hex: String = canonical.iter().map(|b| format!("{:02x}", b)).collect();
+                Ok(Addr::unchecked(format!("pubkey{}", hex)))
+            } else {
+                self.0.addr_humanize(canonical)
+            }
+        }
+
+        fn secp256k1_verify(
+            &self,
+            message_hash: &[u8],
+            signature: &[u8],
+            public_key: &[u8],
+        ) -> Result<bool, VerificationError> {
+            self.0.secp256k1_verify(message_hash, signature, public_key)
+        }
+
+        fn secp256k1_recover_pubkey(
+            &self,
+            message_hash: &[u8],
+            signature: &[u8],
+            recovery_param: u8,
+        ) -> Result<Vec<u8>, RecoverPubkeyError> {
+            self.0
+                .secp256k1_recover_pubkey(message_hash, signature, recovery_param)
+        }
+
+        fn ed25519_verify(
+            &self,
+            message: &[u8],
+            signature: &[u8],
+            public_key: &[u8],
+        ) -> Result<bool, VerificationError> {
+            self.0.ed25519_verify(message, signature, public_key)
+        }
+
+        fn ed25519_batch_verify(
+            &self,
+            messages: &[&[u8]],
+            signatures: &[&[u8]],
+            public_keys: &[&[u8]],
+        ) -> Result<bool, VerificationError> {
+            self.0
+                .ed25519_batch_verify(messages, signatures, public_keys)
+        }
+
+        fn debug(&self, message: &str) {
+            self.0.debug(message)
+        }
+    }
+
+    fn sign_permit(key: &SigningKey, message: &PermitMessage) -> Binary {
+        let signature: Signature = key.sign(&to_vec(message).unwrap());
+        Binary::from(signature.as_ref())
+    }
+
+    #[test]
+    fn permit() {
+        let mut deps = OwnedDeps {
+            storage: MockStorage::default(),
+            api: PubkeyApi::default(),
+            querier: MockQuerier::<Empty>::new(&[]),
+        };
+        let key = SigningKey::from_bytes(&[7u8; 32]).unwrap();
+        let pubkey = Binary::from(key.verifying_key().to_bytes().as_slice());
+        let owner = deps
+            .api
+            .addr_humanize(&pubkey_to_canonical(&pubkey).unwrap())
+            .unwrap()
+            .to_string();
+        let spender = String::from("addr0002");
+        let relayer = String::from("relayer");
+        do_instantiate(deps.as_mut(), &owner, Uint128::new(12340000));
+
+        let env = mock_env();
+        let amount = Uint128::new(7777);
+        let expires = Expiration::AtHeight(123456);
+        let message = PermitMessage {
+            chain_id: env.block.chain_id.clone(),
+            contract: env.contract.address.to_string(),
+            spender: spender.clone(),
+            amount,
+            expires: Some(expires),
+            deadline: Expiration::AtHeight(env.block.height + 10),
+            nonce: 0,
+        };
+        let permit = |message: &PermitMessage, signature: Binary| ExecuteMsg::Permit {
+            owner_pubkey: pubkey.clone(),
+            spender: message.spender.clone(),
+            amount: message.amount,
+            expires: message.expires,
+            deadline: message.deadline,
+            nonce: message.nonce,
+            signature,
+        };
+
+        // anyone can submit the signed permit
+        let msg = permit(&message, sign_permit(&key, &message));
+        let info = mock_info(relayer.as_ref(), &[]);
+        let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "permit"),
+                attr("owner", owner.clone()),
+                attr("spender", spender.clone()),
+                attr("amount", amount),
+                attr("nonce", "0"),
+            ]
+        );
+        assert_eq!(
+            query_allowance(deps.as_ref(), owner.clone(), spender.clone()).unwrap(),
+            AllowanceResponse {
+                allowance: amount,
+                expires,
+            }
+        );
+        assert_eq!(
+            query_permit_nonce(deps.as_ref(), owner.clone()).unwrap(),
+            PermitNonceResponse { nonce: 1 }
+        );
+
+        // the permit can't be replayed
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidNonce { expected: 1 });
+
+        // the signature must cover the submitted values
+        let message = PermitMessage {
+            nonce: 1,
+            ..message
+        };
+        let mut msg = permit(&message, sign_permit(&key, &message));
+        if let ExecuteMsg::Permit { amount, .. } = &mut msg {
+            *amount = Uint128::new(1000000);
+        }
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSignature {});
+
+        // and be made by the owner
+        let other_key = SigningKey::from_bytes(&[8u8; 32]).unwrap();
+        let msg = permit(&message, sign_permit(&other_key, &message));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSignature {});
+
+        // for this token contract
+        let other_contract = PermitMessage {
+            contract: String::from("other_token"),
+            ..message.clone()
+        };
+        let msg = permit(&message, sign_permit(&key, &other_contract));
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidSignature {});
+
+        // only compressed keys derive to an address
+        let mut msg = permit(&message, sign_permit(&key, &message));
+        if let ExecuteMsg::Permit { owner_pubkey, .. } = &mut msg {
+            *owner_pubkey = Binary::from(key.verifying_key().to_encoded_point(false).as_bytes());
+        }
+        let err = execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidPubkey {});
+
+        // nor after its deadline
+        let msg = permit(&message, sign_permit(&key, &message));
+        let mut late_env = env.clone();
+        late_env.block.height += 10;
+        let err = execute(deps.as_mut(), late_env, info.clone(), msg.clone()).unwrap_err();
+        assert_eq!(err, ContractError::PermitExpired {});
+
+        // the next permit increases the allowance
+        execute(deps.as_mut(), env.clone(), info.clone(), msg).unwrap();
+        assert_eq!(
+            query_allowance(deps.as_ref(), owner.clone(), spender.clone())
+                .unwrap()
+                .allowance,
+            amount + amount
+        );
+
+        // the spender can use it
+        let info = mock_info(spender.as_ref(), &[]);
+        let msg = ExecuteMsg::TransferFrom {
+            owner: owner.clone(),
+            recipient: relayer.clone(),
+            amount,
+        };
+        execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(get_balance(deps.as_ref(), relayer.clone()), amount);
+        assert_eq!(
+            query_allowance(deps.as_ref(), owner.clone(), spender.clone())
+                .unwrap()
+                .allowance,
+            amount
+        );
+
+        // the owner can cancel a permit which is not submitted yet
+        let message = PermitMessage {
+            nonce: 2,
+            ..message
+        };
+        let msg = permit(&message, sign_permit(&key, &message));
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(owner.as_ref(), &[]),
+            ExecuteMsg::IncreaseNonce {},
+        )
+        .unwrap();
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "increase_nonce"),
+                attr("owner", owner.clone()),
+                attr("nonce", "3"),
+            ]
+        );
+        let err = execute(deps.as_mut(), env, mock_info(relayer.as_ref(), &[]), msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidNonce { expected: 3 });
+        assert_eq!(
+            query_allowance(deps.as_ref(), owner, spender)
+                .unwrap()
+                .allowance,
+            amount
+        );
+    }
 }
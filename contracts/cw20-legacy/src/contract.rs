@@ -1,42 +1,113 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
-    Uint128,
+    attr, to_binary, Addr, Api, Binary, Deps, DepsMut, Env, Event, MessageInfo, Order, Response,
+    StdError, StdResult, Storage, Uint128,
 };
+use cw_storage_plus::{Bound, U64Key};
 
-use cw2::set_contract_version;
-use cw20::{BalanceResponse, Cw20Coin, Cw20ReceiveMsg, MinterResponse, TokenInfoResponse};
+use cw2::{set_contract_version, ContractVersion};
+use cw20::{
+    BalanceResponse, Cw20Coin, Cw20ReceiveMsg, DownloadLogoResponse, EmbeddedLogo, Logo, LogoInfo,
+    MarketingInfoResponse, MinterResponse, TokenInfoResponse,
+};
 
 use crate::allowances::{
-    execute_burn_from, execute_decrease_allowance, execute_increase_allowance, execute_send_from,
-    execute_transfer_from, query_allowance,
+    execute_batch_transfer_from, execute_burn_from, execute_decrease_allowance,
+    execute_increase_allowance, execute_increase_nonce, execute_permit, execute_send_from,
+    execute_transfer_from, query_allowance, query_permit_nonce,
 };
 use crate::enumerable::{query_all_accounts, query_all_allowances};
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{MinterData, TokenInfo, BALANCES, TOKEN_INFO};
+use crate::migration::{assert_not_migrated, migrate_storage};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, TotalSupplyResponse};
+use crate::state::{
+    MinterData, TokenInfo, BALANCES, LOGO, MARKETING_INFO, TOKEN_INFO, TOTAL_SUPPLY_HISTORY,
+};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cw20-base";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+const LOGO_SIZE_CAP: usize = 5 * 1024;
+
+// number of balances and allowances moved by a migrate call by default
+const DEFAULT_MIGRATION_LIMIT: u32 = 100;
+
+/// Checks if data starts with XML preamble
+fn verify_xml_preamble(data: &[u8]) -> Result<(), ContractError> {
+    // The easiest way to perform this check would be just match on regex, however regex
+    // compilation is heavy and probably not worth it.
+
+    let preamble = data
+        .split_inclusive(|c| *c == b'>')
+        .next()
+        .ok_or(ContractError::InvalidXmlPreamble {})?;
+
+    const PREFIX: &[u8] = b"<?xml ";
+    const POSTFIX: &[u8] = b"?>";
+
+    if !(preamble.starts_with(PREFIX) && preamble.ends_with(POSTFIX)) {
+        Err(ContractError::InvalidXmlPreamble {})
+    } else {
+        Ok(())
+    }
+}
+
+/// Validates XML logo
+fn verify_xml_logo(logo: &[u8]) -> Result<(), ContractError> {
+    verify_xml_preamble(logo)?;
+
+    if logo.len() > LOGO_SIZE_CAP {
+        Err(ContractError::LogoTooBig {})
+    } else {
+        Ok(())
+    }
+}
+
+/// Validates png logo
+fn verify_png_logo(logo: &[u8]) -> Result<(), ContractError> {
+    // PNG header format:
+    // 0x89 - magic byte, out of ASCII table to fail on 7-bit systems
+    // "PNG" ascii representation
+    // [0x0d, 0x0a] - dos style line ending
+    // 0x1a - dos control character, stop displaying rest of the file
+    // 0x0a - unix style line ending
+    const HEADER: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    if logo.len() > LOGO_SIZE_CAP {
+        Err(ContractError::LogoTooBig {})
+    } else if !logo.starts_with(&HEADER) {
+        Err(ContractError::InvalidPngHeader {})
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks if passed logo is correct, and if not, returns an error
+fn verify_logo(logo: &Logo) -> Result<(), ContractError> {
+    match logo {
+        Logo::Embedded(EmbeddedLogo::Svg(logo)) => verify_xml_logo(logo),
+        Logo::Embedded(EmbeddedLogo::Png(logo)) => verify_png_logo(logo),
+        Logo::Url(_) => Ok(()), // Any reasonable url validation would be regex based, probably not worth it
+    }
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     mut deps: DepsMut,
-    _env: Env,
+    env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     // check valid token info
     msg.validate()?;
     // create initial accounts
-    let total_supply = create_accounts(&mut deps, &msg.initial_balances)?;
+    let total_supply = create_accounts(&mut deps, &env, &msg.initial_balances)?;
 
     if let Some(limit) = msg.get_cap() {
         if total_supply > limit {
-            return Err(StdError::generic_err("Initial supply greater than cap"));
+            return Err(StdError::generic_err("Initial supply greater than cap").into());
         }
     }
 
@@ -57,14 +128,46 @@ pub fn instantiate(
         mint,
     };
     TOKEN_INFO.save(deps.storage, &data)?;
+    TOTAL_SUPPLY_HISTORY.save(deps.storage, U64Key::new(env.block.height), &total_supply)?;
+
+    if let Some(marketing) = msg.marketing {
+        let logo = if let Some(logo) = marketing.logo {
+            verify_logo(&logo)?;
+            LOGO.save(deps.storage, &logo)?;
+
+            match logo {
+                Logo::Url(url) => Some(LogoInfo::Url(url)),
+                Logo::Embedded(_) => Some(LogoInfo::Embedded),
+            }
+        } else {
+            None
+        };
+
+        let data = MarketingInfoResponse {
+            project: marketing.project,
+            description: marketing.description,
+            marketing: marketing
+                .marketing
+                .map(|addr| deps.api.addr_validate(&addr))
+                .transpose()?,
+            logo,
+        };
+        MARKETING_INFO.save(deps.storage, &data)?;
+    }
+
     Ok(Response::default())
 }
 
-pub fn create_accounts(deps: &mut DepsMut, accounts: &[Cw20Coin]) -> StdResult<Uint128> {
+pub fn create_accounts(deps: &mut DepsMut, env: &Env, accounts: &[Cw20Coin]) -> StdResult<Uint128> {
     let mut total_supply = Uint128::zero();
     for row in accounts {
         let address = deps.api.addr_canonicalize(&row.address)?;
-        BALANCES.save(deps.storage, address.as_slice(), &row.amount)?;
+        BALANCES.save(
+            deps.storage,
+            address.as_slice(),
+            &row.amount,
+            env.block.height,
+        )?;
         total_supply += row.amount;
     }
     Ok(total_supply)
@@ -77,10 +180,15 @@ pub fn execute(
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    assert_not_migrated(deps.storage)?;
+
     match msg {
         ExecuteMsg::Transfer { recipient, amount } => {
             execute_transfer(deps, env, info, recipient, amount)
         }
+        ExecuteMsg::BatchTransfer { transfers } => {
+            execute_batch_transfer(deps, env, info, transfers)
+        }
         ExecuteMsg::Burn { amount } => execute_burn(deps, env, info, amount),
         ExecuteMsg::Send {
             contract,
@@ -98,11 +206,34 @@ pub fn execute(
             amount,
             expires,
         } => execute_decrease_allowance(deps, env, info, spender, amount, expires),
+        ExecuteMsg::Permit {
+            owner_pubkey,
+            spender,
+            amount,
+            expires,
+            deadline,
+            nonce,
+            signature,
+        } => execute_permit(
+            deps,
+            env,
+            owner_pubkey,
+            spender,
+            amount,
+            expires,
+            deadline,
+            nonce,
+            signature,
+        ),
+        ExecuteMsg::IncreaseNonce {} => execute_increase_nonce(deps, info),
         ExecuteMsg::TransferFrom {
             owner,
             recipient,
             amount,
         } => execute_transfer_from(deps, env, info, owner, recipient, amount),
+        ExecuteMsg::BatchTransferFrom { owner, transfers } => {
+            execute_batch_transfer_from(deps, env, info, owner, transfers)
+        }
         ExecuteMsg::BurnFrom { owner, amount } => execute_burn_from(deps, env, info, owner, amount),
         ExecuteMsg::SendFrom {
             owner,
@@ -110,12 +241,18 @@ pub fn execute(
             amount,
             msg,
         } => execute_send_from(deps, env, info, owner, contract, amount, msg),
+        ExecuteMsg::UpdateMarketing {
+            project,
+            description,
+            marketing,
+        } => execute_update_marketing(deps, env, info, project, description, marketing),
+        ExecuteMsg::UploadLogo(logo) => execute_upload_logo(deps, env, info, logo),
     }
 }
 
 pub fn execute_transfer(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     recipient: String,
     amount: Uint128,
@@ -131,6 +268,7 @@ pub fn execute_transfer(
         deps.api
             .addr_canonicalize(&info.sender.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> {
             Ok(balance.unwrap_or_default().checked_sub(amount)?)
         },
@@ -140,6 +278,7 @@ pub fn execute_transfer(
         deps.api
             .addr_canonicalize(&rcpt_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
     )?;
 
@@ -151,9 +290,87 @@ pub fn execute_transfer(
     ]))
 }
 
+pub fn execute_batch_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    transfers: Vec<(String, Uint128)>,
+) -> Result<Response, ContractError> {
+    let total = batch_amount(&transfers)?;
+    let events = move_batch(
+        deps.storage,
+        deps.api,
+        env.block.height,
+        &info.sender,
+        total,
+        transfers,
+    )?;
+
+    Ok(Response::new()
+        .add_attributes(vec![
+            attr("action", "batch_transfer"),
+            attr("from", info.sender),
+            attr("amount", total),
+        ])
+        .add_events(events))
+}
+
+/// Returns the sum of the amounts of a batch transfer, which must be non-empty and non-zero
+pub(crate) fn batch_amount(transfers: &[(String, Uint128)]) -> Result<Uint128, ContractError> {
+    if transfers.is_empty() {
+        return Err(ContractError::EmptyTransfers {});
+    }
+
+    let mut total = Uint128::zero();
+    for (_, amount) in transfers {
+        if amount.is_zero() {
+            return Err(ContractError::InvalidZeroAmount {});
+        }
+        total = total.checked_add(*amount).map_err(StdError::from)?;
+    }
+    Ok(total)
+}
+
+/// Debits `total` from the balance of `from` once and credits each recipient of `transfers`,
+/// returning a "transfer" event per recipient
+pub(crate) fn move_batch(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    height: u64,
+    from: &Addr,
+    total: Uint128,
+    transfers: Vec<(String, Uint128)>,
+) -> Result<Vec<Event>, ContractError> {
+    BALANCES.update(
+        storage,
+        api.addr_canonicalize(from.as_str())?.as_slice(),
+        height,
+        |balance: Option<Uint128>| -> StdResult<_> {
+            Ok(balance.unwrap_or_default().checked_sub(total)?)
+        },
+    )?;
+
+    let mut events = Vec::with_capacity(transfers.len());
+    for (recipient, amount) in transfers {
+        let rcpt_addr = api.addr_validate(&recipient)?;
+        BALANCES.update(
+            storage,
+            api.addr_canonicalize(rcpt_addr.as_str())?.as_slice(),
+            height,
+            |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
+        )?;
+        events.push(Event::new("transfer").add_attributes(vec![
+            attr("from", from),
+            attr("to", recipient),
+            attr("amount", amount),
+        ]));
+    }
+    Ok(events)
+}
+
 pub fn execute_burn(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     amount: Uint128,
 ) -> Result<Response, ContractError> {
@@ -167,15 +384,21 @@ pub fn execute_burn(
         deps.api
             .addr_canonicalize(&info.sender.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> {
             Ok(balance.unwrap_or_default().checked_sub(amount)?)
         },
     )?;
     // reduce total_supply
-    TOKEN_INFO.update(deps.storage, |mut info| -> StdResult<_> {
+    let token_info = TOKEN_INFO.update(deps.storage, |mut info| -> StdResult<_> {
         info.total_supply = info.total_supply.checked_sub(amount)?;
         Ok(info)
     })?;
+    TOTAL_SUPPLY_HISTORY.save(
+        deps.storage,
+        U64Key::new(env.block.height),
+        &token_info.total_supply,
+    )?;
 
     Ok(Response::new().add_attributes(vec![
         attr("action", "burn"),
@@ -186,7 +409,7 @@ pub fn execute_burn(
 
 pub fn execute_mint(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     recipient: String,
     amount: Uint128,
@@ -211,6 +434,11 @@ pub fn execute_mint(
         }
     }
     TOKEN_INFO.save(deps.storage, &config)?;
+    TOTAL_SUPPLY_HISTORY.save(
+        deps.storage,
+        U64Key::new(env.block.height),
+        &config.total_supply,
+    )?;
 
     // add amount to recipient balance
     let rcpt_addr = deps.api.addr_validate(&recipient)?;
@@ -219,6 +447,7 @@ pub fn execute_mint(
         deps.api
             .addr_canonicalize(&rcpt_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
     )?;
 
@@ -231,7 +460,7 @@ pub fn execute_mint(
 
 pub fn execute_send(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     contract: String,
     amount: Uint128,
@@ -249,6 +478,7 @@ pub fn execute_send(
         deps.api
             .addr_canonicalize(&info.sender.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> {
             Ok(balance.unwrap_or_default().checked_sub(amount)?)
         },
@@ -258,6 +488,7 @@ pub fn execute_send(
         deps.api
             .addr_canonicalize(&rcpt_addr.to_string())?
             .as_slice(),
+        env.block.height,
         |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
     )?;
 
@@ -278,15 +509,110 @@ pub fn execute_send(
     ))
 }
 
+pub fn execute_update_marketing(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    project: Option<String>,
+    description: Option<String>,
+    marketing: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut marketing_info = MARKETING_INFO
+        .may_load(deps.storage)?
+        .ok_or(ContractError::Unauthorized {})?;
+
+    if marketing_info
+        .marketing
+        .as_ref()
+        .ok_or(ContractError::Unauthorized {})?
+        != &info.sender
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    match project {
+        Some(empty) if empty.trim().is_empty() => marketing_info.project = None,
+        Some(project) => marketing_info.project = Some(project),
+        None => (),
+    }
+
+    match description {
+        Some(empty) if empty.trim().is_empty() => marketing_info.description = None,
+        Some(description) => marketing_info.description = Some(description),
+        None => (),
+    }
+
+    match marketing {
+        Some(empty) if empty.trim().is_empty() => marketing_info.marketing = None,
+        Some(marketing) => marketing_info.marketing = Some(deps.api.addr_validate(&marketing)?),
+        None => (),
+    }
+
+    if marketing_info.project.is_none()
+        && marketing_info.description.is_none()
+        && marketing_info.marketing.is_none()
+        && marketing_info.logo.is_none()
+    {
+        MARKETING_INFO.remove(deps.storage);
+    } else {
+        MARKETING_INFO.save(deps.storage, &marketing_info)?;
+    }
+
+    Ok(Response::new().add_attribute("action", "update_marketing"))
+}
+
+pub fn execute_upload_logo(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    logo: Logo,
+) -> Result<Response, ContractError> {
+    let mut marketing_info = MARKETING_INFO
+        .may_load(deps.storage)?
+        .ok_or(ContractError::Unauthorized {})?;
+
+    verify_logo(&logo)?;
+
+    if marketing_info
+        .marketing
+        .as_ref()
+        .ok_or(ContractError::Unauthorized {})?
+        != &info.sender
+    {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    LOGO.save(deps.storage, &logo)?;
+
+    let logo_info = match logo {
+        Logo::Url(url) => LogoInfo::Url(url),
+        Logo::Embedded(_) => LogoInfo::Embedded,
+    };
+
+    marketing_info.logo = Some(logo_info);
+    MARKETING_INFO.save(deps.storage, &marketing_info)?;
+
+    Ok(Response::new().add_attribute("action", "upload_logo"))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    assert_not_migrated(deps.storage).map_err(|err| StdError::generic_err(err.to_string()))?;
+
     match msg {
         QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
+        QueryMsg::BalanceAtHeight { address, height } => {
+            to_binary(&query_balance_at_height(deps, address, height)?)
+        }
+        QueryMsg::TotalSupplyAtHeight { height } => {
+            to_binary(&query_total_supply_at_height(deps, height)?)
+        }
         QueryMsg::TokenInfo {} => to_binary(&query_token_info(deps)?),
         QueryMsg::Minter {} => to_binary(&query_minter(deps)?),
         QueryMsg::Allowance { owner, spender } => {
             to_binary(&query_allowance(deps, owner, spender)?)
         }
+        QueryMsg::PermitNonce { owner } => to_binary(&query_permit_nonce(deps, owner)?),
         QueryMsg::AllAllowances {
             owner,
             start_after,
@@ -295,9 +621,68 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
         QueryMsg::AllAccounts { start_after, limit } => {
             to_binary(&query_all_accounts(deps, start_after, limit)?)
         }
+        QueryMsg::MarketingInfo {} => to_binary(&query_marketing_info(deps)?),
+        QueryMsg::DownloadLogo {} => to_binary(&query_download_logo(deps)?),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    match msg {
+        MigrateMsg::Upgrade {} => upgrade(deps, env),
+        MigrateMsg::MigrateStorage {
+            limit,
+            contract_version,
+        } => migrate_to_cw20_base(deps, env, limit, contract_version),
     }
 }
 
+pub fn upgrade(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    assert_not_migrated(deps.storage)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    // tokens instantiated by older versions have no history yet
+    if TOTAL_SUPPLY_HISTORY
+        .range(deps.storage, None, None, Order::Ascending)
+        .next()
+        .is_none()
+    {
+        let total_supply = TOKEN_INFO.load(deps.storage)?.total_supply;
+        TOTAL_SUPPLY_HISTORY.save(deps.storage, U64Key::new(env.block.height), &total_supply)?;
+    }
+
+    Ok(Response::new().add_attribute("action", "upgrade"))
+}
+
+pub fn migrate_to_cw20_base(
+    deps: DepsMut,
+    _env: Env,
+    limit: Option<u32>,
+    contract_version: Option<ContractVersion>,
+) -> Result<Response, ContractError> {
+    let migration = migrate_storage(
+        deps.storage,
+        deps.api,
+        limit.unwrap_or(DEFAULT_MIGRATION_LIMIT),
+    )?;
+
+    // the legacy code can't operate on the migrated storage anymore
+    if migration.completed {
+        let version = contract_version.unwrap_or(ContractVersion {
+            contract: CONTRACT_NAME.to_string(),
+            version: CONTRACT_VERSION.to_string(),
+        });
+        set_contract_version(deps.storage, version.contract, version.version)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "migrate"),
+        attr("balances", migration.balances.to_string()),
+        attr("allowances", migration.allowances.to_string()),
+        attr("completed", migration.completed.to_string()),
+    ]))
+}
+
 pub fn query_balance(deps: Deps, address: String) -> StdResult<BalanceResponse> {
     let address = deps.api.addr_canonicalize(&address)?;
     let balance = BALANCES
@@ -306,6 +691,38 @@ pub fn query_balance(deps: Deps, address: String) -> StdResult<BalanceResponse>
     Ok(BalanceResponse { balance })
 }
 
+pub fn query_balance_at_height(
+    deps: Deps,
+    address: String,
+    height: u64,
+) -> StdResult<BalanceResponse> {
+    // the balances are only tracked as long as the total supply is
+    query_total_supply_at_height(deps, height)?;
+
+    let address = deps.api.addr_canonicalize(&address)?;
+    let balance = BALANCES
+        .may_load_at_height(deps.storage, address.as_slice(), height)?
+        .unwrap_or_default();
+    Ok(BalanceResponse { balance })
+}
+
+pub fn query_total_supply_at_height(deps: Deps, height: u64) -> StdResult<TotalSupplyResponse> {
+    let total_supply = TOTAL_SUPPLY_HISTORY
+        .range(
+            deps.storage,
+            None,
+            Some(Bound::exclusive_int(height)),
+            Order::Descending,
+        )
+        .next()
+        .transpose()?
+        .map(|(_, total_supply)| total_supply)
+        .ok_or_else(|| {
+            StdError::generic_err(format!("No snapshot is available at height {}", height))
+        })?;
+    Ok(TotalSupplyResponse { total_supply })
+}
+
 pub fn query_token_info(deps: Deps) -> StdResult<TokenInfoResponse> {
     let info = TOKEN_INFO.load(deps.storage)?;
     let res = TokenInfoResponse {
@@ -329,12 +746,32 @@ pub fn query_minter(deps: Deps) -> StdResult<Option<MinterResponse>> {
     Ok(minter)
 }
 
+pub fn query_marketing_info(deps: Deps) -> StdResult<MarketingInfoResponse> {
+    Ok(MARKETING_INFO.may_load(deps.storage)?.unwrap_or_default())
+}
+
+pub fn query_download_logo(deps: Deps) -> StdResult<DownloadLogoResponse> {
+    let logo = LOGO.load(deps.storage)?;
+    match logo {
+        Logo::Embedded(EmbeddedLogo::Svg(logo)) => Ok(DownloadLogoResponse {
+            mime_type: "image/svg+xml".to_owned(),
+            data: logo,
+        }),
+        Logo::Embedded(EmbeddedLogo::Png(logo)) => Ok(DownloadLogoResponse {
+            mime_type: "image/png".to_owned(),
+            data: logo,
+        }),
+        Logo::Url(_) => Err(StdError::not_found("logo")),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
     use cosmwasm_std::{coins, from_binary, CosmosMsg, StdError, SubMsg, WasmMsg};
 
     use super::*;
+    use crate::msg::InstantiateMarketingInfo;
 
     fn get_balance<T: Into<String>>(deps: Deps, address: T) -> Uint128 {
         query_balance(deps, address.into()).unwrap().balance
@@ -380,6 +817,7 @@ mod tests {
                 amount,
             }],
             mint: mint.clone(),
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
@@ -414,6 +852,7 @@ mod tests {
                 amount,
             }],
             mint: None,
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
@@ -453,6 +892,7 @@ mod tests {
                 minter: minter.clone(),
                 cap: Some(limit),
             }),
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
@@ -499,13 +939,14 @@ mod tests {
                 minter,
                 cap: Some(limit),
             }),
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
         let err = instantiate(deps.as_mut(), env, info, instantiate_msg).unwrap_err();
         assert_eq!(
             err,
-            StdError::generic_err("Initial supply greater than cap")
+            StdError::generic_err("Initial supply greater than cap").into()
         );
     }
 
@@ -614,6 +1055,7 @@ mod tests {
                 },
             ],
             mint: None,
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
@@ -730,6 +1172,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn batch_transfer() {
+        let mut deps = mock_dependencies(&[]);
+        let addr1 = String::from("addr0001");
+        let addr2 = String::from("addr0002");
+        let addr3 = String::from("addr0003");
+        let amount1 = Uint128::new(12340000);
+        do_instantiate(deps.as_mut(), &addr1, amount1);
+
+        // cannot transfer nothing
+        let info = mock_info(addr1.as_ref(), &[]);
+        let msg = ExecuteMsg::BatchTransfer { transfers: vec![] };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(err, ContractError::EmptyTransfers {});
+
+        // cannot transfer a zero amount
+        let info = mock_info(addr1.as_ref(), &[]);
+        let msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![
+                (addr2.clone(), Uint128::new(100)),
+                (addr3.clone(), Uint128::zero()),
+            ],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(err, ContractError::InvalidZeroAmount {});
+
+        // cannot send more than we have in total
+        let info = mock_info(addr1.as_ref(), &[]);
+        let msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![
+                (addr2.clone(), Uint128::new(12000000)),
+                (addr3.clone(), Uint128::new(1000000)),
+            ],
+        };
+        let err = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert!(matches!(err, ContractError::Std(StdError::Overflow { .. })));
+
+        // valid batch, with a recipient credited twice
+        let mut env = mock_env();
+        env.block.height += 1;
+        let info = mock_info(addr1.as_ref(), &[]);
+        let msg = ExecuteMsg::BatchTransfer {
+            transfers: vec![
+                (addr2.clone(), Uint128::new(1000)),
+                (addr3.clone(), Uint128::new(2000)),
+                (addr2.clone(), Uint128::new(500)),
+            ],
+        };
+        let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 0);
+        assert_eq!(
+            res.attributes,
+            vec![
+                attr("action", "batch_transfer"),
+                attr("from", addr1.clone()),
+                attr("amount", Uint128::new(3500)),
+            ]
+        );
+        assert_eq!(res.events.len(), 3);
+        assert_eq!(
+            res.events[1],
+            Event::new("transfer").add_attributes(vec![
+                attr("from", addr1.clone()),
+                attr("to", addr3.clone()),
+                attr("amount", Uint128::new(2000)),
+            ])
+        );
+
+        assert_eq!(
+            get_balance(deps.as_ref(), addr1.clone()),
+            Uint128::new(12336500)
+        );
+        assert_eq!(
+            get_balance(deps.as_ref(), addr2.clone()),
+            Uint128::new(1500)
+        );
+        assert_eq!(
+            get_balance(deps.as_ref(), addr3.clone()),
+            Uint128::new(2000)
+        );
+        assert_eq!(
+            query_token_info(deps.as_ref()).unwrap().total_supply,
+            amount1
+        );
+
+        // the snapshots are taken at the height of the batch
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), addr1.clone(), env.block.height),
+            amount1
+        );
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), addr2.clone(), env.block.height),
+            Uint128::zero()
+        );
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), addr1, env.block.height + 1),
+            Uint128::new(12336500)
+        );
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), addr2, env.block.height + 1),
+            Uint128::new(1500)
+        );
+    }
+
     #[test]
     fn burn() {
         let mut deps = mock_dependencies(&coins(2, "token"));
@@ -852,4 +1398,457 @@ mod tests {
             amount1
         );
     }
+
+    fn get_balance_at_height<T: Into<String>>(deps: Deps, address: T, height: u64) -> Uint128 {
+        query_balance_at_height(deps, address.into(), height)
+            .unwrap()
+            .balance
+    }
+
+    fn get_total_supply_at_height(deps: Deps, height: u64) -> Uint128 {
+        query_total_supply_at_height(deps, height)
+            .unwrap()
+            .total_supply
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut deps = mock_dependencies(&[]);
+        let addr1 = String::from("addr0001");
+        let addr2 = String::from("addr0002");
+        let minter = String::from("minter");
+        let mut env = mock_env();
+        let start = env.block.height;
+
+        do_instantiate_with_minter(deps.as_mut(), &addr1, Uint128::new(1000), &minter, None);
+
+        // nothing is known before the instantiation
+        let err = query_total_supply_at_height(deps.as_ref(), start).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err(format!("No snapshot is available at height {}", start))
+        );
+        query_balance_at_height(deps.as_ref(), addr1.clone(), start).unwrap_err();
+
+        env.block.height = start + 1;
+        let msg = ExecuteMsg::Transfer {
+            recipient: addr2.clone(),
+            amount: Uint128::new(100),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(&addr1, &[]), msg).unwrap();
+        let msg = ExecuteMsg::Mint {
+            recipient: addr2.clone(),
+            amount: Uint128::new(500),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(&minter, &[]), msg).unwrap();
+
+        env.block.height = start + 3;
+        let msg = ExecuteMsg::Burn {
+            amount: Uint128::new(200),
+        };
+        execute(deps.as_mut(), env.clone(), mock_info(&addr2, &[]), msg).unwrap();
+
+        // the values at the beginning of each height
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), &addr1, start + 1),
+            Uint128::new(1000)
+        );
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), &addr2, start + 1),
+            Uint128::zero()
+        );
+        assert_eq!(
+            get_total_supply_at_height(deps.as_ref(), start + 1),
+            Uint128::new(1000)
+        );
+        for height in [start + 2, start + 3] {
+            assert_eq!(
+                get_balance_at_height(deps.as_ref(), &addr1, height),
+                Uint128::new(900)
+            );
+            assert_eq!(
+                get_balance_at_height(deps.as_ref(), &addr2, height),
+                Uint128::new(600)
+            );
+            assert_eq!(
+                get_total_supply_at_height(deps.as_ref(), height),
+                Uint128::new(1500)
+            );
+        }
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), &addr2, start + 4),
+            Uint128::new(400)
+        );
+        assert_eq!(
+            get_total_supply_at_height(deps.as_ref(), start + 4),
+            Uint128::new(1300)
+        );
+
+        let data = query(
+            deps.as_ref(),
+            env,
+            QueryMsg::TotalSupplyAtHeight { height: start + 4 },
+        )
+        .unwrap();
+        let loaded: TotalSupplyResponse = from_binary(&data).unwrap();
+        assert_eq!(loaded.total_supply, Uint128::new(1300));
+    }
+
+    #[test]
+    fn upgrade_starts_snapshots() {
+        let mut deps = mock_dependencies(&[]);
+        let addr1 = String::from("addr0001");
+        do_instantiate(deps.as_mut(), &addr1, Uint128::new(1000));
+
+        // tokens instantiated by older versions have no history
+        let start = mock_env().block.height;
+        TOTAL_SUPPLY_HISTORY.remove(deps.as_mut().storage, U64Key::new(start));
+
+        let mut env = mock_env();
+        env.block.height = start + 10;
+        let res = migrate(deps.as_mut(), env, MigrateMsg::Upgrade {}).unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "upgrade")]);
+
+        query_total_supply_at_height(deps.as_ref(), start + 10).unwrap_err();
+        assert_eq!(
+            get_total_supply_at_height(deps.as_ref(), start + 11),
+            Uint128::new(1000)
+        );
+        assert_eq!(
+            get_balance_at_height(deps.as_ref(), &addr1, start + 11),
+            Uint128::new(1000)
+        );
+    }
+
+    mod marketing {
+        use super::*;
+
+        use cosmwasm_std::Addr;
+
+        const PNG_HEADER: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+        fn do_instantiate_with_marketing(deps: DepsMut, marketing: &str) {
+            let instantiate_msg = InstantiateMsg {
+                name: "Cash Token".to_string(),
+                symbol: "CASH".to_string(),
+                decimals: 9,
+                initial_balances: vec![],
+                mint: None,
+                marketing: Some(InstantiateMarketingInfo {
+                    project: Some("Project".to_owned()),
+                    description: Some("Description".to_owned()),
+                    marketing: Some(marketing.to_owned()),
+                    logo: Some(Logo::Url("url".to_owned())),
+                }),
+            };
+            let info = mock_info("creator", &[]);
+            let env = mock_env();
+            let res = instantiate(deps, env, info, instantiate_msg).unwrap();
+            assert_eq!(0, res.messages.len());
+        }
+
+        fn assert_no_logo_data(deps: Deps) {
+            let err = query_download_logo(deps).unwrap_err();
+            assert!(
+                matches!(err, StdError::NotFound { .. }),
+                "Expected StdError::NotFound, received {}",
+                err
+            );
+        }
+
+        #[test]
+        fn instantiate_with_marketing() {
+            let mut deps = mock_dependencies(&[]);
+            do_instantiate_with_marketing(deps.as_mut(), "marketing");
+
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap(),
+                MarketingInfoResponse {
+                    project: Some("Project".to_owned()),
+                    description: Some("Description".to_owned()),
+                    marketing: Some(Addr::unchecked("marketing")),
+                    logo: Some(LogoInfo::Url("url".to_owned())),
+                }
+            );
+            assert_no_logo_data(deps.as_ref());
+
+            // without marketing info nobody can update it
+            let mut deps = mock_dependencies(&[]);
+            do_instantiate(deps.as_mut(), "genesis", Uint128::new(1234));
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap(),
+                MarketingInfoResponse::default()
+            );
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("genesis", &[]),
+                ExecuteMsg::UpdateMarketing {
+                    project: Some("New project".to_owned()),
+                    description: None,
+                    marketing: None,
+                },
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::Unauthorized {});
+        }
+
+        #[test]
+        fn instantiate_with_invalid_logo() {
+            let mut deps = mock_dependencies(&[]);
+            let instantiate_msg = InstantiateMsg {
+                name: "Cash Token".to_string(),
+                symbol: "CASH".to_string(),
+                decimals: 9,
+                initial_balances: vec![],
+                mint: None,
+                marketing: Some(InstantiateMarketingInfo {
+                    project: None,
+                    description: None,
+                    marketing: Some("marketing".to_owned()),
+                    logo: Some(Logo::Embedded(EmbeddedLogo::Png([1].into()))),
+                }),
+            };
+            let info = mock_info("creator", &[]);
+            let env = mock_env();
+            let err = instantiate(deps.as_mut(), env, info, instantiate_msg).unwrap_err();
+            assert_eq!(err, ContractError::InvalidPngHeader {});
+        }
+
+        #[test]
+        fn update_unauthorised() {
+            let mut deps = mock_dependencies(&[]);
+            do_instantiate_with_marketing(deps.as_mut(), "marketing");
+
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::UpdateMarketing {
+                    project: Some("New project".to_owned()),
+                    description: Some("Better description".to_owned()),
+                    marketing: Some("creator".to_owned()),
+                },
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::Unauthorized {});
+
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::UploadLogo(Logo::Url("new_url".to_owned())),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::Unauthorized {});
+
+            // ensure marketing didn't change
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap(),
+                MarketingInfoResponse {
+                    project: Some("Project".to_owned()),
+                    description: Some("Description".to_owned()),
+                    marketing: Some(Addr::unchecked("marketing")),
+                    logo: Some(LogoInfo::Url("url".to_owned())),
+                }
+            );
+        }
+
+        #[test]
+        fn update_marketing() {
+            let mut deps = mock_dependencies(&[]);
+            do_instantiate_with_marketing(deps.as_mut(), "creator");
+
+            // unset fields are left unchanged, empty ones are cleared
+            let res = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::UpdateMarketing {
+                    project: Some("New project".to_owned()),
+                    description: Some("".to_owned()),
+                    marketing: Some("marketing".to_owned()),
+                },
+            )
+            .unwrap();
+            assert_eq!(res.messages, vec![]);
+
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap(),
+                MarketingInfoResponse {
+                    project: Some("New project".to_owned()),
+                    description: None,
+                    marketing: Some(Addr::unchecked("marketing")),
+                    logo: Some(LogoInfo::Url("url".to_owned())),
+                }
+            );
+
+            // the previous marketing address lost its role
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::UpdateMarketing {
+                    project: None,
+                    description: None,
+                    marketing: Some("".to_owned()),
+                },
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::Unauthorized {});
+
+            // invalid address is rejected
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("marketing", &[]),
+                ExecuteMsg::UpdateMarketing {
+                    project: None,
+                    description: None,
+                    marketing: Some("m".to_owned()),
+                },
+            )
+            .unwrap_err();
+            assert!(
+                matches!(err, ContractError::Std(_)),
+                "Expected Std error, received: {}",
+                err
+            );
+
+            let _res = execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("marketing", &[]),
+                ExecuteMsg::UpdateMarketing {
+                    project: None,
+                    description: None,
+                    marketing: Some("".to_owned()),
+                },
+            )
+            .unwrap();
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap(),
+                MarketingInfoResponse {
+                    project: Some("New project".to_owned()),
+                    description: None,
+                    marketing: None,
+                    logo: Some(LogoInfo::Url("url".to_owned())),
+                }
+            );
+        }
+
+        #[test]
+        fn upload_logo() {
+            let mut deps = mock_dependencies(&[]);
+            do_instantiate_with_marketing(deps.as_mut(), "creator");
+            let info = mock_info("creator", &[]);
+
+            let res = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::UploadLogo(Logo::Embedded(EmbeddedLogo::Png(PNG_HEADER.into()))),
+            )
+            .unwrap();
+            assert_eq!(res.messages, vec![]);
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap().logo,
+                Some(LogoInfo::Embedded)
+            );
+            assert_eq!(
+                query_download_logo(deps.as_ref()).unwrap(),
+                DownloadLogoResponse {
+                    mime_type: "image/png".to_owned(),
+                    data: PNG_HEADER.into(),
+                }
+            );
+
+            let img = "<?xml version=\"1.0\"?><svg></svg>".as_bytes();
+            let _res = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::UploadLogo(Logo::Embedded(EmbeddedLogo::Svg(img.into()))),
+            )
+            .unwrap();
+            assert_eq!(
+                query_download_logo(deps.as_ref()).unwrap(),
+                DownloadLogoResponse {
+                    mime_type: "image/svg+xml".to_owned(),
+                    data: img.into(),
+                }
+            );
+
+            // url logo has nothing to download
+            let _res = execute(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                ExecuteMsg::UploadLogo(Logo::Url("new_url".to_owned())),
+            )
+            .unwrap();
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap().logo,
+                Some(LogoInfo::Url("new_url".to_owned()))
+            );
+            assert_no_logo_data(deps.as_ref());
+        }
+
+        #[test]
+        fn upload_invalid_logo() {
+            let mut deps = mock_dependencies(&[]);
+            do_instantiate_with_marketing(deps.as_mut(), "creator");
+            let info = mock_info("creator", &[]);
+
+            let img = [&PNG_HEADER[..], &[1; 6000][..]].concat();
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::UploadLogo(Logo::Embedded(EmbeddedLogo::Png(img.into()))),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::LogoTooBig {});
+
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::UploadLogo(Logo::Embedded(EmbeddedLogo::Png([1].into()))),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::InvalidPngHeader {});
+
+            let img = [
+                "<?xml version=\"1.0\"?><svg>".as_bytes(),
+                &[b'a'; 6000][..],
+                "</svg>".as_bytes(),
+            ]
+            .concat();
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info.clone(),
+                ExecuteMsg::UploadLogo(Logo::Embedded(EmbeddedLogo::Svg(img.into()))),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::LogoTooBig {});
+
+            let err = execute(
+                deps.as_mut(),
+                mock_env(),
+                info,
+                ExecuteMsg::UploadLogo(Logo::Embedded(EmbeddedLogo::Svg(b"<svg></svg>".into()))),
+            )
+            .unwrap_err();
+            assert_eq!(err, ContractError::InvalidXmlPreamble {});
+
+            // the logo is left unchanged
+            assert_eq!(
+                query_marketing_info(deps.as_ref()).unwrap().logo,
+                Some(LogoInfo::Url("url".to_owned()))
+            );
+            assert_no_logo_data(deps.as_ref());
+        }
+    }
 }
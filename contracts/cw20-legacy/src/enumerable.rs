@@ -60,14 +60,15 @@ pub fn query_all_accounts(
     let start =
         calc_range_start_human(deps.api, start_after.map(Addr::unchecked))?.map(Bound::exclusive);
 
-    let accounts: Result<Vec<_>, _> = BALANCES
-        .keys(deps.storage, start, None, Order::Ascending)
-        .map(|k| {
+    let accounts: StdResult<Vec<_>> = BALANCES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (k, _) = item?;
             deps.api
                 .addr_humanize(&CanonicalAddr::from(k))
                 .map(|v| v.to_string())
         })
-        .take(limit)
         .collect();
 
     Ok(AllAccountsResponse {
@@ -98,6 +99,7 @@ mod tests {
                 amount,
             }],
             mint: None,
+            marketing: None,
         };
         let info = mock_info("creator", &[]);
         let env = mock_env();
@@ -15,6 +15,9 @@ pub enum ContractError {
     #[error("Invalid zero amount")]
     InvalidZeroAmount {},
 
+    #[error("No transfers given")]
+    EmptyTransfers {},
+
     #[error("Allowance is expired")]
     Expired {},
 
@@ -23,4 +26,31 @@ pub enum ContractError {
 
     #[error("Minting cannot exceed the cap")]
     CannotExceedCap {},
+
+    #[error("Logo binary data exceeds 5KB limit")]
+    LogoTooBig {},
+
+    #[error("Invalid xml preamble for SVG")]
+    InvalidXmlPreamble {},
+
+    #[error("Invalid png header")]
+    InvalidPngHeader {},
+
+    #[error("Invalid public key; a compressed secp256k1 key is required")]
+    InvalidPubkey {},
+
+    #[error("Invalid permit signature")]
+    InvalidSignature {},
+
+    #[error("Invalid permit nonce; expected {expected}")]
+    InvalidNonce { expected: u64 },
+
+    #[error("Permit deadline has passed")]
+    PermitExpired {},
+
+    #[error("Storage migration to the cw20-base layout is in progress")]
+    MigrationInProgress {},
+
+    #[error("Storage is migrated to the cw20-base layout")]
+    StorageMigrated {},
 }
@@ -0,0 +1,396 @@
+use cosmwasm_std::{Addr, Api, CanonicalAddr, Order, StdResult, Storage, Uint128};
+use cw20_base::state as base;
+use cw_storage_plus::Map;
+
+use crate::error::ContractError;
+use crate::state::{
+    MigrationInfo, MigrationStage, ALLOWANCES, MIGRATION, STAGED_ALLOWANCES, STAGED_BALANCES,
+    TOKEN_INFO,
+};
+
+// the current balances of `BALANCES`, moved without recording snapshots for them
+const BALANCES: Map<&[u8], Uint128> = Map::new("balance");
+
+/// Moves up to `limit` balances and allowances from the canonical address layout to the
+/// cw20-base `Addr` layout. The token info is converted on the first call.
+pub fn migrate_storage(
+    storage: &mut dyn Storage,
+    api: &dyn Api,
+    limit: u32,
+) -> Result<MigrationInfo, ContractError> {
+    let mut migration = match MIGRATION.may_load(storage)? {
+        Some(migration) if migration.completed => return Err(ContractError::StorageMigrated {}),
+        Some(migration) => migration,
+        None => {
+            migrate_token_info(storage, api)?;
+            MigrationInfo {
+                stage: MigrationStage::StageBalances,
+                balances: 0,
+                allowances: 0,
+                completed: false,
+            }
+        }
+    };
+
+    let mut remaining = limit as usize;
+    while remaining > 0 && !migration.completed {
+        let moved = match migration.stage {
+            MigrationStage::StageBalances => stage_balances(storage, api, remaining)?,
+            MigrationStage::StageAllowances => stage_allowances(storage, api, remaining)?,
+            MigrationStage::MoveBalances => {
+                let moved = move_balances(storage, remaining)?;
+                migration.balances += moved as u64;
+                moved
+            }
+            MigrationStage::MoveAllowances => {
+                let moved = move_allowances(storage, remaining)?;
+                migration.allowances += moved as u64;
+                moved
+            }
+        };
+
+        // the stage is drained once it can't fill the batch
+        if moved < remaining {
+            match migration.stage {
+                MigrationStage::StageBalances => migration.stage = MigrationStage::StageAllowances,
+                MigrationStage::StageAllowances => migration.stage = MigrationStage::MoveBalances,
+                MigrationStage::MoveBalances => migration.stage = MigrationStage::MoveAllowances,
+                MigrationStage::MoveAllowances => migration.completed = true,
+            }
+        }
+        remaining -= moved;
+    }
+
+    MIGRATION.save(storage, &migration)?;
+    Ok(migration)
+}
+
+/// Refuses to operate on the legacy layout once the storage migration has started
+pub fn assert_not_migrated(storage: &dyn Storage) -> Result<(), ContractError> {
+    match MIGRATION.may_load(storage)? {
+        Some(migration) if migration.completed => Err(ContractError::StorageMigrated {}),
+        Some(_) => Err(ContractError::MigrationInProgress {}),
+        None => Ok(()),
+    }
+}
+
+fn migrate_token_info(storage: &mut dyn Storage, api: &dyn Api) -> StdResult<()> {
+    let info = TOKEN_INFO.load(storage)?;
+    let mint = match info.mint {
+        Some(m) => Some(base::MinterData {
+            minter: api.addr_humanize(&m.minter)?,
+            cap: m.cap,
+        }),
+        None => None,
+    };
+
+    base::TOKEN_INFO.save(
+        storage,
+        &base::TokenInfo {
+            name: info.name,
+            symbol: info.symbol,
+            decimals: info.decimals,
+            total_supply: info.total_supply,
+            mint,
+        },
+    )?;
+    TOKEN_INFO.remove(storage);
+    Ok(())
+}
+
+fn stage_balances(storage: &mut dyn Storage, api: &dyn Api, limit: usize) -> StdResult<usize> {
+    let balances = BALANCES
+        .range(storage, None, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (key, balance) in balances.iter() {
+        let address = api.addr_humanize(&CanonicalAddr::from(key.as_slice()))?;
+        STAGED_BALANCES.save(storage, &address, balance)?;
+        BALANCES.remove(storage, key);
+    }
+    Ok(balances.len())
+}
+
+fn stage_allowances(storage: &mut dyn Storage, api: &dyn Api, limit: usize) -> StdResult<usize> {
+    let allowances = ALLOWANCES
+        .range(storage, None, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (key, allowance) in allowances.iter() {
+        let (owner_raw, spender_raw) = split_pair_key(key);
+        let owner = api.addr_humanize(&CanonicalAddr::from(owner_raw))?;
+        let spender = api.addr_humanize(&CanonicalAddr::from(spender_raw))?;
+        STAGED_ALLOWANCES.save(storage, (&owner, &spender), allowance)?;
+        ALLOWANCES.remove(storage, (owner_raw, spender_raw));
+    }
+    Ok(allowances.len())
+}
+
+fn move_balances(storage: &mut dyn Storage, limit: usize) -> StdResult<usize> {
+    let balances = STAGED_BALANCES
+        .range(storage, None, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (key, balance) in balances.iter() {
+        let address = Addr::unchecked(String::from_utf8(key.clone())?);
+        base::BALANCES.save(storage, &address, balance)?;
+        STAGED_BALANCES.remove(storage, &address);
+    }
+    Ok(balances.len())
+}
+
+fn move_allowances(storage: &mut dyn Storage, limit: usize) -> StdResult<usize> {
+    let allowances = STAGED_ALLOWANCES
+        .range(storage, None, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    for (key, allowance) in allowances.iter() {
+        let (owner, spender) = split_pair_key(key);
+        let owner = Addr::unchecked(String::from_utf8(owner.to_vec())?);
+        let spender = Addr::unchecked(String::from_utf8(spender.to_vec())?);
+        base::ALLOWANCES.save(storage, (&owner, &spender), allowance)?;
+        STAGED_ALLOWANCES.remove(storage, (&owner, &spender));
+    }
+    Ok(allowances.len())
+}
+
+/// Splits a `(first, second)` map key returned by `range`, where `first` is length-prefixed
+fn split_pair_key(key: &[u8]) -> (&[u8], &[u8]) {
+    let len = u16::from_be_bytes([key[0], key[1]]) as usize;
+    (&key[2..2 + len], &key[2 + len..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{DepsMut, StdError, Uint128};
+    use cw2::{get_contract_version, ContractVersion};
+    use cw20::{AllowanceResponse, Cw20Coin, Expiration, MinterResponse};
+
+    use crate::contract::{execute, instantiate, migrate, query};
+    use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+
+    fn do_instantiate(mut deps: DepsMut, accounts: &[(&str, u128)]) {
+        let instantiate_msg = InstantiateMsg {
+            name: "Auto Gen".to_string(),
+            symbol: "AUTO".to_string(),
+            decimals: 3,
+            initial_balances: accounts
+                .iter()
+                .map(|(address, amount)| Cw20Coin {
+                    address: address.to_string(),
+                    amount: Uint128::new(*amount),
+                })
+                .collect(),
+            mint: Some(MinterResponse {
+                minter: "minter".to_string(),
+                cap: None,
+            }),
+            marketing: None,
+        };
+        let info = mock_info("creator", &[]);
+        let env = mock_env();
+        instantiate(deps.branch(), env, info, instantiate_msg).unwrap();
+    }
+
+    fn increase_allowance(deps: DepsMut, owner: &str, spender: &str, amount: u128) {
+        let msg = ExecuteMsg::IncreaseAllowance {
+            spender: spender.to_string(),
+            amount: Uint128::new(amount),
+            expires: Some(Expiration::AtHeight(123456)),
+        };
+        execute(deps, mock_env(), mock_info(owner, &[]), msg).unwrap();
+    }
+
+    fn migrate_msg(limit: u32) -> MigrateMsg {
+        MigrateMsg::MigrateStorage {
+            limit: Some(limit),
+            contract_version: Some(ContractVersion {
+                contract: "crates.io:cw20-base".to_string(),
+                version: "0.8.0".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn migrate_in_batches() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(
+            deps.as_mut(),
+            &[("addr0001", 1000), ("addr0002", 2000), ("addr0003", 3000)],
+        );
+        increase_allowance(deps.as_mut(), "addr0001", "spender1", 100);
+        increase_allowance(deps.as_mut(), "addr0001", "spender2", 200);
+        increase_allowance(deps.as_mut(), "addr0002", "spender1", 300);
+
+        // 3 balances and 3 allowances are staged and moved in 4 batches of 4
+        for _ in 0..3 {
+            let res = migrate(deps.as_mut(), mock_env(), migrate_msg(4)).unwrap();
+            assert_eq!(res.attributes.last().unwrap().value, "false");
+        }
+
+        // the legacy layout is not usable while migrating
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr0001", &[]),
+            ExecuteMsg::Transfer {
+                recipient: "addr0002".to_string(),
+                amount: Uint128::new(1),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::MigrationInProgress {});
+        let err = query(
+            deps.as_ref(),
+            mock_env(),
+            QueryMsg::Balance {
+                address: "addr0001".to_string(),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err("Storage migration to the cw20-base layout is in progress")
+        );
+
+        let res = migrate(deps.as_mut(), mock_env(), migrate_msg(4)).unwrap();
+        assert_eq!(res.attributes.last().unwrap().value, "true");
+        assert_eq!(
+            MIGRATION.load(&deps.storage).unwrap(),
+            MigrationInfo {
+                stage: MigrationStage::MoveAllowances,
+                balances: 3,
+                allowances: 3,
+                completed: true,
+            }
+        );
+
+        // nothing is left behind
+        let owner_raw = deps.api.addr_canonicalize("addr0001").unwrap();
+        let spender_raw = deps.api.addr_canonicalize("spender1").unwrap();
+        assert!(BALANCES
+            .may_load(&deps.storage, owner_raw.as_slice())
+            .unwrap()
+            .is_none());
+        assert!(ALLOWANCES
+            .may_load(
+                &deps.storage,
+                (owner_raw.as_slice(), spender_raw.as_slice())
+            )
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            STAGED_BALANCES
+                .range(&deps.storage, None, None, Order::Ascending)
+                .count(),
+            0
+        );
+        assert_eq!(
+            STAGED_ALLOWANCES
+                .range(&deps.storage, None, None, Order::Ascending)
+                .count(),
+            0
+        );
+        assert!(TOKEN_INFO.may_load(&deps.storage).unwrap().is_none());
+
+        // the entries are readable by cw20-base
+        for (address, amount) in [("addr0001", 1000), ("addr0002", 2000), ("addr0003", 3000)] {
+            assert_eq!(
+                base::BALANCES
+                    .load(&deps.storage, &Addr::unchecked(address))
+                    .unwrap(),
+                Uint128::new(amount)
+            );
+        }
+        for (owner, spender, amount) in [
+            ("addr0001", "spender1", 100),
+            ("addr0001", "spender2", 200),
+            ("addr0002", "spender1", 300),
+        ] {
+            assert_eq!(
+                base::ALLOWANCES
+                    .load(
+                        &deps.storage,
+                        (&Addr::unchecked(owner), &Addr::unchecked(spender))
+                    )
+                    .unwrap(),
+                AllowanceResponse {
+                    allowance: Uint128::new(amount),
+                    expires: Expiration::AtHeight(123456),
+                }
+            );
+        }
+        assert_eq!(
+            base::TOKEN_INFO.load(&deps.storage).unwrap(),
+            base::TokenInfo {
+                name: "Auto Gen".to_string(),
+                symbol: "AUTO".to_string(),
+                decimals: 3,
+                total_supply: Uint128::new(6000),
+                mint: Some(base::MinterData {
+                    minter: Addr::unchecked("minter"),
+                    cap: None,
+                }),
+            }
+        );
+        assert_eq!(
+            get_contract_version(&deps.storage).unwrap(),
+            ContractVersion {
+                contract: "crates.io:cw20-base".to_string(),
+                version: "0.8.0".to_string(),
+            }
+        );
+
+        // the switch is one-way
+        let err = migrate(deps.as_mut(), mock_env(), migrate_msg(4)).unwrap_err();
+        assert_eq!(err, ContractError::StorageMigrated {});
+        let err = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr0001", &[]),
+            ExecuteMsg::Burn {
+                amount: Uint128::new(1),
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::StorageMigrated {});
+    }
+
+    #[test]
+    fn migrate_at_once() {
+        let mut deps = mock_dependencies(&[]);
+        do_instantiate(deps.as_mut(), &[("addr0001", 1000)]);
+        increase_allowance(deps.as_mut(), "addr0001", "spender1", 100);
+
+        let res = migrate(
+            deps.as_mut(),
+            mock_env(),
+            MigrateMsg::MigrateStorage {
+                limit: None,
+                contract_version: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(res.attributes.last().unwrap().value, "true");
+        assert_eq!(
+            base::BALANCES
+                .load(&deps.storage, &Addr::unchecked("addr0001"))
+                .unwrap(),
+            Uint128::new(1000)
+        );
+        assert_eq!(
+            get_contract_version(&deps.storage).unwrap(),
+            ContractVersion {
+                contract: "crates.io:cw20-base".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            }
+        );
+    }
+}
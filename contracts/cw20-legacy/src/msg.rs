@@ -1,8 +1,17 @@
 use cosmwasm_std::{Binary, StdError, StdResult, Uint128};
-use cw20::{Cw20Coin, Expiration, MinterResponse};
+use cw2::ContractVersion;
+use cw20::{Cw20Coin, Expiration, Logo, MinterResponse};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct InstantiateMarketingInfo {
+    pub project: Option<String>,
+    pub description: Option<String>,
+    pub marketing: Option<String>,
+    pub logo: Option<Logo>,
+}
+
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct InstantiateMsg {
     pub name: String,
@@ -10,6 +19,7 @@ pub struct InstantiateMsg {
     pub decimals: u8,
     pub initial_balances: Vec<Cw20Coin>,
     pub mint: Option<MinterResponse>,
+    pub marketing: Option<InstantiateMarketingInfo>,
 }
 
 impl InstantiateMsg {
@@ -62,6 +72,8 @@ fn is_valid_symbol(symbol: &str) -> bool {
 pub enum ExecuteMsg {
     /// Transfer is a base message to move tokens to another account without triggering actions
     Transfer { recipient: String, amount: Uint128 },
+    /// Moves each amount of `transfers` to its recipient, debiting the sender once
+    BatchTransfer { transfers: Vec<(String, Uint128)> },
     /// Burn is a base message to destroy tokens forever
     Burn { amount: Uint128 },
     /// Send is a base message to transfer tokens to a contract and trigger an action
@@ -90,6 +102,23 @@ pub enum ExecuteMsg {
         amount: Uint128,
         expires: Option<Expiration>,
     },
+    /// Only with "approval" extension. Allows spender to access an additional amount tokens
+    /// from the account of `owner_pubkey`, like `IncreaseAllowance` signed by the owner.
+    /// `signature` is the secp256k1 signature of the sha256 hash of `PermitMessage`,
+    /// `nonce` must be the next nonce of the owner and the permit can't be submitted
+    /// once `deadline` has passed.
+    Permit {
+        owner_pubkey: Binary,
+        spender: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+        deadline: Expiration,
+        nonce: u64,
+        signature: Binary,
+    },
+    /// Only with "approval" extension. Skips the next permit nonce of env.sender,
+    /// so a permit signed with it can no longer be submitted.
+    IncreaseNonce {},
     /// Only with "approval" extension. Transfers amount tokens from owner -> recipient
     /// if `env.sender` has sufficient pre-approval.
     TransferFrom {
@@ -97,6 +126,12 @@ pub enum ExecuteMsg {
         recipient: String,
         amount: Uint128,
     },
+    /// Only with "approval" extension. Moves each amount of `transfers` from owner to its
+    /// recipient if `env.sender` has sufficient pre-approval for the sum of the amounts.
+    BatchTransferFrom {
+        owner: String,
+        transfers: Vec<(String, Uint128)>,
+    },
     /// Only with "approval" extension. Sends amount tokens from owner -> contract
     /// if `env.sender` has sufficient pre-approval.
     SendFrom {
@@ -107,6 +142,19 @@ pub enum ExecuteMsg {
     },
     /// Only with "approval" extension. Destroys tokens forever
     BurnFrom { owner: String, amount: Uint128 },
+    /// Only with the "marketing" extension. If authorized, updates marketing metadata.
+    /// Setting None/null for any of these will leave it unchanged.
+    /// Setting Some("") will clear this field on the contract storage
+    UpdateMarketing {
+        /// A URL pointing to the project behind this token.
+        project: Option<String>,
+        /// A longer description of the token and it's utility. Designed for tooltips or such
+        description: Option<String>,
+        /// The address (if any) who can update this data structure
+        marketing: Option<String>,
+    },
+    /// If set as the "marketing" role on the contract, upload a new URL, SVG, or PNG for the token
+    UploadLogo(Logo),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -126,6 +174,10 @@ pub enum QueryMsg {
     /// Returns how much spender can use from owner account, 0 if unset.
     /// Return type: AllowanceResponse.
     Allowance { owner: String, spender: String },
+    /// Only with "allowance" extension.
+    /// Returns the nonce the next permit of the owner must be signed with.
+    /// Return type: PermitNonceResponse.
+    PermitNonce { owner: String },
     /// Only with "enumerable" extension (and "allowances")
     /// Returns all allowances this owner has approved. Supports pagination.
     /// Return type: AllAllowancesResponse.
@@ -141,4 +193,60 @@ pub enum QueryMsg {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    /// Returns the balance of the given address at the beginning of the given height, 0 if unset.
+    /// Only available from the height after the instantiation or the upgrade to this version.
+    /// Return type: BalanceResponse.
+    BalanceAtHeight { address: String, height: u64 },
+    /// Returns the total supply at the beginning of the given height.
+    /// Only available from the height after the instantiation or the upgrade to this version.
+    /// Return type: TotalSupplyResponse.
+    TotalSupplyAtHeight { height: u64 },
+    /// Only with "marketing" extension
+    /// Returns more metadata on the contract to display in the client,
+    /// such as description, logo and project url.
+    /// Return type: MarketingInfoResponse.
+    MarketingInfo {},
+    /// Only with "marketing" extension
+    /// Downloads the embedded logo data (if stored on chain). Errors if no logo data stored for this
+    /// contract.
+    /// Return type: DownloadLogoResponse.
+    DownloadLogo {},
+}
+
+/// The message signed by the owner for `ExecuteMsg::Permit`, serialized as JSON in this
+/// field order. It is bound to the chain and the token contract.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitMessage {
+    pub chain_id: String,
+    pub contract: String,
+    pub spender: String,
+    pub amount: Uint128,
+    pub expires: Option<Expiration>,
+    pub deadline: Expiration,
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PermitNonceResponse {
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TotalSupplyResponse {
+    pub total_supply: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrateMsg {
+    /// Upgrades the contract to this version, keeping the legacy storage layout
+    Upgrade {},
+    /// Moves the balances and allowances to the cw20-base layout, see README
+    MigrateStorage {
+        /// Maximum number of balances and allowances moved by this call.
+        limit: Option<u32>,
+        /// cw2 version info stored once the migration completes, e.g. to allow a migration
+        /// to the cw20-base code afterwards. Defaults to the version info of this contract.
+        contract_version: Option<ContractVersion>,
+    },
 }
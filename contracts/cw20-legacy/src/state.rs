@@ -1,10 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, Uint128};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, CanonicalAddr, Uint128};
+use cw_storage_plus::{Item, Map, SnapshotMap, Strategy, U64Key};
 
-use cw20::AllowanceResponse;
+use cw20::{AllowanceResponse, Logo, MarketingInfoResponse};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -30,8 +30,49 @@ impl TokenInfo {
 }
 
 pub const TOKEN_INFO: Item<TokenInfo> = Item::new("\u{0}\ntoken_info");
-pub const BALANCES: Map<&[u8], Uint128> = Map::new("balance");
+pub const BALANCES: SnapshotMap<&[u8], Uint128> = SnapshotMap::new(
+    "balance",
+    "balance__checkpoints",
+    "balance__changelog",
+    Strategy::EveryBlock,
+);
+/// Total supply at the end of each height it changed
+pub const TOTAL_SUPPLY_HISTORY: Map<U64Key, Uint128> = Map::new("total_supply_history");
 pub const ALLOWANCES: Map<(&[u8], &[u8]), AllowanceResponse> = Map::new("allowance");
+/// Next permit nonce of each owner
+pub const PERMIT_NONCES: Map<&[u8], u64> = Map::new("permit_nonce");
+pub const MARKETING_INFO: Item<MarketingInfoResponse> = Item::new("marketing_info");
+pub const LOGO: Item<Logo> = Item::new("logo");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStage {
+    /// Moving `BALANCES` out of the canonical address layout
+    StageBalances,
+    /// Moving `ALLOWANCES` out of the canonical address layout
+    StageAllowances,
+    /// Moving the staged balances into the cw20-base layout
+    MoveBalances,
+    /// Moving the staged allowances into the cw20-base layout
+    MoveAllowances,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct MigrationInfo {
+    pub stage: MigrationStage,
+    /// number of balances moved into the cw20-base layout
+    pub balances: u64,
+    /// number of allowances moved into the cw20-base layout
+    pub allowances: u64,
+    pub completed: bool,
+}
+
+/// Set once the storage migration starts, the legacy layout is no longer usable from then
+pub const MIGRATION: Item<MigrationInfo> = Item::new("migration");
+/// Entries on their way to the cw20-base layout. They can't be written to the `balance` and
+/// `allowance` namespaces directly, where they would be mixed up with the entries to migrate.
+pub const STAGED_BALANCES: Map<&Addr, Uint128> = Map::new("staged_balance");
+pub const STAGED_ALLOWANCES: Map<(&Addr, &Addr), AllowanceResponse> = Map::new("staged_allowance");
 
 #[cfg(test)]
 mod test {
@@ -3,7 +3,9 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 use token_vesting::msg::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, VestingAccountResponse,
+    ConfigResponse, Cw20HookMsg, DenomSummaryResponse, ExecuteMsg, InstantiateMsg, MigrateMsg,
+    OperatorsResponse, QueryMsg, StakeResponse, VestingAccountResponse, VestingAccountsResponse,
+    VestingSimulationResponse, VestingSummaryResponse, VotingPowerResponse,
 };
 
 fn main() {
@@ -15,6 +17,15 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema(&schema_for!(ExecuteMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
     export_schema(&schema_for!(Cw20HookMsg), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
     export_schema(&schema_for!(VestingAccountResponse), &out_dir);
+    export_schema(&schema_for!(VestingAccountsResponse), &out_dir);
+    export_schema(&schema_for!(VestingSummaryResponse), &out_dir);
+    export_schema(&schema_for!(DenomSummaryResponse), &out_dir);
+    export_schema(&schema_for!(OperatorsResponse), &out_dir);
+    export_schema(&schema_for!(VestingSimulationResponse), &out_dir);
+    export_schema(&schema_for!(StakeResponse), &out_dir);
+    export_schema(&schema_for!(VotingPowerResponse), &out_dir);
 }
@@ -1,33 +1,100 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
+
 use cosmwasm_std::{
-    from_binary, to_binary, Attribute, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Env,
-    MessageInfo, Order, Response, StdError, StdResult, Uint128, WasmMsg,
+    from_binary, to_binary, Attribute, BankMsg, Binary, BlockInfo, Coin, CosmosMsg, Deps, DepsMut,
+    Env, MessageInfo, Order, Reply, Response, StdError, StdResult, Storage, Timestamp, Uint128,
+    Uint64, WasmMsg,
 };
 
 use serde_json::to_string;
 
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom};
-use cw_storage_plus::Bound;
+use cw2::{set_contract_version, CONTRACT};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom, Expiration};
+use cw_storage_plus::{Bound, PrimaryKey, U64Key};
+use semver::Version;
 
+use crate::error::ContractError;
+use crate::migration::{assert_not_migrating, migrate_storage, start_migration};
 use crate::msg::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, VestingAccountResponse, VestingData,
-    VestingSchedule,
+    ConfigResponse, Cw20HookMsg, DenomSummaryResponse, ExecuteMsg, InstantiateMsg, MigrateMsg,
+    OperatorInfo, OperatorsResponse, QueryMsg, VestingAccountData, VestingAccountMsg,
+    VestingAccountResponse, VestingAccountsResponse, VestingData, VestingSchedule,
+    VestingSimulation, VestingSimulationResponse, VestingSummaryResponse, VotingPowerResponse,
+};
+use crate::staking::{
+    assert_released_covered, delegate, query_stake, released_amount, returnable_amount,
+    staked_amount, undelegate, unstaked_amount, withdraw_rewards, withdraw_rewards_reply,
+    WITHDRAW_REWARDS_REPLY_ID,
 };
-use crate::state::{denom_to_key, VestingAccount, VESTING_ACCOUNTS};
+use crate::state::{
+    denom_to_key, grants_of, load_grant, load_grants, next_grant_id, remove_vesting_account,
+    save_vesting_account, vesting_accounts, Config, Operator, VestingAccount, CONFIG,
+    DEFAULT_UNBONDING_PERIOD, DENOM_SUMMARIES, MIGRATION, OPERATORS, PENDING_MASTERS,
+    PENDING_TRANSFERS, VOTING_POWERS,
+};
+
+// version info for migration info
+const CONTRACT_NAME: &str = "crates.io:token-vesting";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// number of entries processed by a migrate call by default
+const DEFAULT_MIGRATION_LIMIT: u32 = 100;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
-    _deps: DepsMut,
+    deps: DepsMut,
     _env: Env,
-    _info: MessageInfo,
-    _msg: InstantiateMsg,
-) -> StdResult<Response> {
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    let unbonding_period = msg.unbonding_period.unwrap_or(DEFAULT_UNBONDING_PERIOD);
+    if unbonding_period == 0 {
+        return Err(ContractError::InvalidUnbondingPeriod {});
+    }
+
+    CONFIG.save(
+        deps.storage,
+        &Config {
+            unbonding_period,
+            admin: Some(
+                deps.api
+                    .addr_validate(&msg.admin.unwrap_or_else(|| info.sender.to_string()))?
+                    .to_string(),
+            ),
+            paused: false,
+            allowed_denoms: msg.allowed_denoms,
+        },
+    )?;
+
     Ok(Response::new())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> StdResult<Response> {
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    assert_not_migrating(deps.storage)?;
+
+    // only the admin operations are available while paused
+    match &msg {
+        ExecuteMsg::Pause {}
+        | ExecuteMsg::Unpause {}
+        | ExecuteMsg::UpdateAllowedDenoms { .. }
+        | ExecuteMsg::UpdateAdmin { .. }
+        | ExecuteMsg::UpdateUnbondingPeriod { .. } => {}
+        _ => {
+            if CONFIG.load(deps.storage)?.paused {
+                return Err(ContractError::Paused {});
+            }
+        }
+    }
+
     match msg {
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::RegisterVestingAccount {
@@ -37,7 +104,7 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
         } => {
             // deposit validation
             if info.funds.len() != 1 {
-                return Err(StdError::generic_err("must deposit only one type of token"));
+                return Err(ContractError::InvalidDeposit {});
             }
 
             let deposit_coin = info.funds[0].clone();
@@ -51,9 +118,36 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
                 vesting_schedule,
             )
         }
+        ExecuteMsg::RegisterVestingAccounts { vesting_accounts } => {
+            // deposit validation
+            if info.funds.len() != 1 {
+                return Err(ContractError::InvalidDeposit {});
+            }
+
+            let deposit_coin = info.funds[0].clone();
+            register_vesting_accounts(
+                deps,
+                env,
+                Denom::Native(deposit_coin.denom),
+                deposit_coin.amount,
+                vesting_accounts,
+            )
+        }
+        ExecuteMsg::RegisterVestingAccountsFrom {
+            token,
+            vesting_accounts,
+        } => {
+            // the deposit is pulled from the allowance
+            if !info.funds.is_empty() {
+                return Err(ContractError::InvalidDeposit {});
+            }
+
+            register_vesting_accounts_from(deps, env, info, token, vesting_accounts)
+        }
         ExecuteMsg::DeregisterVestingAccount {
             address,
             denom,
+            grant_id,
             vested_token_recipient,
             left_vesting_token_recipient,
         } => deregister_vesting_account(
@@ -62,10 +156,109 @@ pub fn execute(deps: DepsMut, env: Env, info: MessageInfo, msg: ExecuteMsg) -> S
             info,
             address,
             denom,
+            grant_id,
             vested_token_recipient,
             left_vesting_token_recipient,
         ),
-        ExecuteMsg::Claim { denoms, recipient } => claim(deps, env, info, denoms, recipient),
+        ExecuteMsg::TerminateVesting {
+            address,
+            denom,
+            grant_id,
+            left_vesting_token_recipient,
+        } => terminate_vesting(
+            deps,
+            env,
+            info,
+            address,
+            denom,
+            grant_id,
+            left_vesting_token_recipient,
+        ),
+        ExecuteMsg::ApproveVestingAccountTransfer {
+            address,
+            denom,
+            grant_id,
+        } => approve_vesting_account_transfer(deps, env, info, address, denom, grant_id),
+        ExecuteMsg::ProposeNewMaster {
+            address,
+            denom,
+            grant_id,
+            new_master_address,
+        } => propose_new_master(deps, info, address, denom, grant_id, new_master_address),
+        ExecuteMsg::AcceptMaster {
+            address,
+            denom,
+            grant_id,
+        } => accept_master(deps, info, address, denom, grant_id),
+        ExecuteMsg::AmendVestingAccount {
+            address,
+            denom,
+            grant_id,
+            end_time,
+        } => {
+            // top up deposit validation
+            let top_up_amount = match info.funds.len() {
+                0 => Uint128::zero(),
+                1 if info.funds[0].denom == native_denom(&denom)? => info.funds[0].amount,
+                _ => return Err(ContractError::InvalidDeposit {}),
+            };
+
+            amend_vesting_account(
+                deps,
+                env,
+                info.sender.to_string(),
+                address,
+                denom,
+                grant_id,
+                end_time,
+                top_up_amount,
+            )
+        }
+        ExecuteMsg::Claim {
+            denoms,
+            grant_ids,
+            recipient,
+            msg,
+        } => claim(deps, env, info, denoms, grant_ids, recipient, msg),
+        ExecuteMsg::AuthorizeOperator {
+            operator,
+            recipient,
+            expires,
+        } => authorize_operator(deps, env, info, operator, recipient, expires),
+        ExecuteMsg::RevokeOperator { operator } => revoke_operator(deps, info, operator),
+        ExecuteMsg::Delegate {
+            denom,
+            grant_id,
+            validator,
+            amount,
+        } => delegate(deps, env, info, denom, grant_id, validator, amount),
+        ExecuteMsg::Undelegate {
+            address,
+            denom,
+            grant_id,
+            validator,
+            amount,
+        } => undelegate(deps, env, info, address, denom, grant_id, validator, amount),
+        ExecuteMsg::WithdrawRewards {
+            denom,
+            grant_id,
+            validator,
+        } => withdraw_rewards(deps, env, info, denom, grant_id, validator),
+        ExecuteMsg::ClaimFor { address, denoms } => claim_for(deps, env, info, address, denoms),
+        ExecuteMsg::Pause {} => set_paused(deps, info, true),
+        ExecuteMsg::Unpause {} => set_paused(deps, info, false),
+        ExecuteMsg::UpdateAllowedDenoms { allowed_denoms } => {
+            update_allowed_denoms(deps, info, allowed_denoms)
+        }
+        ExecuteMsg::UpdateAdmin { admin } => update_admin(deps, info, admin),
+        ExecuteMsg::UpdateUnbondingPeriod { unbonding_period } => {
+            update_unbonding_period(deps, info, unbonding_period)
+        }
+        ExecuteMsg::TransferVestingAccount {
+            denom,
+            grant_id,
+            new_address,
+        } => transfer_vesting_account(deps, env, info, denom, grant_id, new_address),
     }
 }
 
@@ -77,113 +270,46 @@ fn register_vesting_account(
     deposit_denom: Denom,
     deposit_amount: Uint128,
     vesting_schedule: VestingSchedule,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let denom_key = denom_to_key(deposit_denom.clone());
 
-    // vesting_account existence check
-    if VESTING_ACCOUNTS.has(deps.storage, (address.as_str(), &denom_key)) {
-        return Err(StdError::generic_err("already exists"));
+    let config = CONFIG.load(deps.storage)?;
+    if let Some(allowed_denoms) = config.allowed_denoms {
+        if !allowed_denoms.contains(&deposit_denom) {
+            return Err(ContractError::DenomNotAllowed {
+                denom: to_string(&deposit_denom).unwrap(),
+            });
+        }
     }
 
     // validate vesting schedule
-    match vesting_schedule.clone() {
-        VestingSchedule::LinearVesting {
-            start_time,
-            end_time,
-            vesting_amount,
-        } => {
-            if vesting_amount.is_zero() {
-                return Err(StdError::generic_err("assert(vesting_amount > 0)"));
-            }
-
-            let start_time = start_time
-                .parse::<u64>()
-                .map_err(|_| StdError::generic_err("invalid start_time"))?;
-
-            let end_time = end_time
-                .parse::<u64>()
-                .map_err(|_| StdError::generic_err("invalid end_time"))?;
-
-            if start_time < env.block.time.seconds() {
-                return Err(StdError::generic_err("assert(start_time < block_time)"));
-            }
-
-            if end_time <= start_time {
-                return Err(StdError::generic_err("assert(end_time <= start_time)"));
-            }
-
-            if vesting_amount != deposit_amount {
-                return Err(StdError::generic_err(
-                    "assert(deposit_amount == vesting_amount)",
-                ));
-            }
-        }
-        VestingSchedule::PeriodicVesting {
-            start_time,
-            end_time,
-            vesting_interval,
-            amount,
-        } => {
-            if amount.is_zero() {
-                return Err(StdError::generic_err(
-                    "cannot make zero token vesting account",
-                ));
-            }
-
-            let start_time = start_time
-                .parse::<u64>()
-                .map_err(|_| StdError::generic_err("invalid start_time"))?;
-
-            let end_time = end_time
-                .parse::<u64>()
-                .map_err(|_| StdError::generic_err("invalid end_time"))?;
-
-            let vesting_interval = vesting_interval
-                .parse::<u64>()
-                .map_err(|_| StdError::generic_err("invalid vesting_interval"))?;
-
-            if start_time < env.block.time.seconds() {
-                return Err(StdError::generic_err("invalid start_time"));
-            }
+    vesting_schedule.validate(&env.block, deposit_amount)?;
 
-            if end_time <= start_time {
-                return Err(StdError::generic_err("assert(end_time > start_time)"));
-            }
-
-            if vesting_interval == 0 {
-                return Err(StdError::generic_err("assert(vesting_interval != 0)"));
-            }
-
-            let time_period = end_time - start_time;
-            if time_period != (time_period / vesting_interval) * vesting_interval {
-                return Err(StdError::generic_err(
-                    "assert((end_time - start_time) % vesting_interval == 0)",
-                ));
-            }
-
-            let num_interval = 1 + time_period / vesting_interval;
-            let vesting_amount = amount.checked_mul(Uint128::from(num_interval))?;
-            if vesting_amount != deposit_amount {
-                return Err(StdError::generic_err(
-                    "assert(deposit_amount = amount * ((end_time - start_time) / vesting_interval + 1))",
-                ));
-            }
-        }
-    }
-
-    VESTING_ACCOUNTS.save(
+    // every registration is a new grant, even for an existing (address, denom)
+    let grant_id = next_grant_id(deps.storage)?;
+    save_vesting_account(
         deps.storage,
-        (address.as_str(), &denom_key),
         &VestingAccount {
+            grant_id,
             master_address: master_address.clone(),
             address: address.to_string(),
             vesting_denom: deposit_denom.clone(),
             vesting_amount: deposit_amount,
             vesting_schedule,
             claimed_amount: Uint128::zero(),
+            terminated_at: None,
+            pending_return_amount: Uint128::zero(),
+            return_address: None,
         },
+        env.block.height,
     )?;
 
+    DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+        let mut summary = summary.unwrap_or_default();
+        summary.deposited_amount = summary.deposited_amount.checked_add(deposit_amount)?;
+        Ok(summary)
+    })?;
+
     Ok(Response::new().add_attributes(vec![
         ("action", "register_vesting_account"),
         (
@@ -191,157 +317,410 @@ fn register_vesting_account(
             master_address.unwrap_or_default().as_str(),
         ),
         ("address", address.as_str()),
+        ("grant_id", &grant_id.to_string()),
         ("vesting_denom", &to_string(&deposit_denom).unwrap()),
         ("vesting_amount", &deposit_amount.to_string()),
     ]))
 }
 
-fn deregister_vesting_account(
+fn register_vesting_accounts(
+    mut deps: DepsMut,
+    env: Env,
+    deposit_denom: Denom,
+    deposit_amount: Uint128,
+    vesting_accounts: Vec<VestingAccountMsg>,
+) -> Result<Response, ContractError> {
+    if vesting_accounts.is_empty() {
+        return Err(ContractError::EmptyVestingAccounts {});
+    }
+
+    let mut vesting_amount = Uint128::zero();
+    for vesting_account in vesting_accounts.iter() {
+        vesting_amount =
+            vesting_amount.checked_add(vesting_account.vesting_schedule.total_amount()?)?;
+    }
+
+    if vesting_amount != deposit_amount {
+        return Err(ContractError::DepositMismatch {
+            expected: vesting_amount,
+            got: deposit_amount,
+        });
+    }
+
+    let mut attrs: Vec<Attribute> = vec![];
+    for vesting_account in vesting_accounts.into_iter() {
+        let vesting_amount = vesting_account.vesting_schedule.total_amount()?;
+        let res = register_vesting_account(
+            deps.branch(),
+            env.clone(),
+            vesting_account.master_address,
+            vesting_account.address,
+            deposit_denom.clone(),
+            vesting_amount,
+            vesting_account.vesting_schedule,
+        )?;
+
+        attrs.extend(
+            res.attributes
+                .into_iter()
+                .filter(|attr| attr.key != "action"),
+        );
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "register_vesting_accounts")
+        .add_attributes(attrs))
+}
+
+fn register_vesting_accounts_from(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    token: String,
+    vesting_accounts: Vec<VestingAccountMsg>,
+) -> Result<Response, ContractError> {
+    let token = deps.api.addr_validate(&token)?;
+
+    let mut vesting_amount = Uint128::zero();
+    for vesting_account in vesting_accounts.iter() {
+        vesting_amount =
+            vesting_amount.checked_add(vesting_account.vesting_schedule.total_amount()?)?;
+    }
+
+    let res = register_vesting_accounts(
+        deps,
+        env.clone(),
+        Denom::Cw20(token.clone()),
+        vesting_amount,
+        vesting_accounts,
+    )?;
+
+    // the registration is reverted when the allowance is not enough
+    Ok(res.add_message(WasmMsg::Execute {
+        contract_addr: token.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+            owner: info.sender.to_string(),
+            recipient: env.contract.address.to_string(),
+            amount: vesting_amount,
+        })?,
+        funds: vec![],
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn deregister_vesting_account(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
     address: String,
     denom: Denom,
+    grant_id: Option<u64>,
     vested_token_recipient: Option<String>,
     left_vesting_token_recipient: Option<String>,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
     let denom_key = denom_to_key(denom.clone());
     let sender = info.sender;
 
     let mut messages: Vec<CosmosMsg> = vec![];
 
     // vesting_account existence check
-    let account = VESTING_ACCOUNTS.may_load(deps.storage, (address.as_str(), &denom_key))?;
-    if account.is_none() {
-        return Err(StdError::generic_err(format!(
-            "vesting entry is not found for denom {:?}",
-            to_string(&denom).unwrap(),
-        )));
+    let accounts = load_grants(deps.storage, &address, &denom, grant_id)?;
+
+    let mut vesting_amount = Uint128::zero();
+    let mut vested_amount = Uint128::zero();
+    let mut claimable_amount = Uint128::zero();
+    let mut released = false;
+    let mut grant_ids: Vec<String> = vec![];
+    for account in accounts.iter() {
+        if account.master_address.is_none() || account.master_address.clone().unwrap() != sender {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        // the staked tokens must be undelegated and released first
+        if !staked_amount(deps.storage, env.block.time.seconds(), account.grant_id)?.is_zero() {
+            return Err(ContractError::StakedFunds {});
+        }
+        released |=
+            !released_amount(deps.storage, env.block.time.seconds(), account.grant_id)?.is_zero();
     }
 
-    let account = account.unwrap();
-    if account.master_address.is_none() || account.master_address.unwrap() != sender {
-        return Err(StdError::generic_err("unauthorized"));
+    // the whole unclaimed amount is paid out, including the released unbondings
+    if let (true, Denom::Native(denom)) = (released, &denom) {
+        let payout = accounts
+            .iter()
+            .try_fold(Uint128::zero(), |payout, account| {
+                payout.checked_add(
+                    account
+                        .vesting_amount
+                        .checked_sub(account.claimed_amount)?
+                        .checked_add(account.pending_return_amount)?,
+                )
+            })?;
+        assert_released_covered(deps.branch(), &env, denom, payout)?;
     }
 
-    // remove vesting account
-    VESTING_ACCOUNTS.remove(deps.storage, (address.as_str(), &denom_key));
+    for account in accounts.iter() {
+        // remove vesting account
+        remove_vesting_account(deps.storage, account.grant_id, env.block.height)?;
 
-    let vested_amount = account
-        .vesting_schedule
-        .vested_amount(env.block.time.seconds())?;
-    let claimed_amount = account.claimed_amount;
+        let grant_vested_amount = account.vested_amount_at(&env.block)?;
+        // the pending return of a terminated grant is sent back with the left vesting amount
+        vesting_amount = vesting_amount
+            .checked_add(account.vesting_amount)?
+            .checked_add(account.pending_return_amount)?;
+        vested_amount = vested_amount.checked_add(grant_vested_amount)?;
+        claimable_amount = claimable_amount
+            .checked_add(grant_vested_amount.checked_sub(account.claimed_amount)?)?;
+        grant_ids.push(account.grant_id.to_string());
+    }
 
     // transfer already vested but not claimed amount to
     // a account address or the given `vested_token_recipient` address
-    let claimable_amount = vested_amount.checked_sub(claimed_amount)?;
     if !claimable_amount.is_zero() {
         let recipient = vested_token_recipient.unwrap_or_else(|| address.to_string());
-        let message: CosmosMsg = match account.vesting_denom.clone() {
-            Denom::Native(denom) => BankMsg::Send {
-                to_address: recipient,
-                amount: vec![Coin {
-                    denom,
-                    amount: claimable_amount,
-                }],
-            }
-            .into(),
-            Denom::Cw20(contract_addr) => WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                    recipient,
-                    amount: claimable_amount,
-                })?,
-                funds: vec![],
-            }
-            .into(),
-        };
-
-        messages.push(message);
+        messages.push(transfer_message(
+            denom.clone(),
+            recipient,
+            claimable_amount,
+        )?);
     }
 
     // transfer left vesting amount to owner or
     // the given `left_vesting_token_recipient` address
-    let left_vesting_amount = account.vesting_amount.checked_sub(vested_amount)?;
+    let left_vesting_amount = vesting_amount.checked_sub(vested_amount)?;
     if !left_vesting_amount.is_zero() {
         let recipient = left_vesting_token_recipient.unwrap_or_else(|| sender.to_string());
-        let message: CosmosMsg = match account.vesting_denom.clone() {
-            Denom::Native(denom) => BankMsg::Send {
-                to_address: recipient,
-                amount: vec![Coin {
-                    denom,
-                    amount: left_vesting_amount,
-                }],
-            }
-            .into(),
-            Denom::Cw20(contract_addr) => WasmMsg::Execute {
-                contract_addr: contract_addr.to_string(),
-                msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                    recipient,
-                    amount: left_vesting_amount,
-                })?,
-                funds: vec![],
-            }
-            .into(),
-        };
-
-        messages.push(message);
+        messages.push(transfer_message(
+            denom.clone(),
+            recipient,
+            left_vesting_amount,
+        )?);
     }
 
+    DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+        let mut summary = summary.unwrap_or_default();
+        summary.claimed_amount = summary.claimed_amount.checked_add(claimable_amount)?;
+        summary.returned_amount = summary.returned_amount.checked_add(left_vesting_amount)?;
+        Ok(summary)
+    })?;
+
     Ok(Response::new().add_messages(messages).add_attributes(vec![
         ("action", "deregister_vesting_account"),
         ("address", address.as_str()),
-        ("vesting_denom", &to_string(&account.vesting_denom).unwrap()),
-        ("vesting_amount", &account.vesting_amount.to_string()),
+        ("grant_ids", &grant_ids.join(",")),
+        ("vesting_denom", &to_string(&denom).unwrap()),
+        ("vesting_amount", &vesting_amount.to_string()),
         ("vested_amount", &vested_amount.to_string()),
         ("left_vesting_amount", &left_vesting_amount.to_string()),
     ]))
 }
 
+fn terminate_vesting(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+    left_vesting_token_recipient: Option<String>,
+) -> Result<Response, ContractError> {
+    let denom_key = denom_to_key(denom.clone());
+    let sender = info.sender;
+
+    // vesting_account existence check
+    let mut account = load_grant(deps.storage, &address, &denom, grant_id)?;
+    if account.master_address.is_none() || account.master_address.clone().unwrap() != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    // a terminated grant is only settled again for its pending return
+    if account.terminated_at.is_some() {
+        if account.pending_return_amount.is_zero() {
+            return Err(ContractError::AlreadyTerminated {});
+        }
+    } else {
+        // the vested amount is kept for the vesting account to claim later
+        let vested_amount = account.vested_amount_at(&env.block)?;
+        account.pending_return_amount = account.vesting_amount.checked_sub(vested_amount)?;
+        account.vesting_amount = vested_amount;
+        account.terminated_at = Some(Uint64::new(env.block.time.seconds()));
+        account.return_address =
+            Some(left_vesting_token_recipient.unwrap_or_else(|| sender.to_string()));
+    }
+
+    // the unvested tokens still bonded or unbonding are returned once released
+    let return_amount = returnable_amount(deps.storage, env.block.time.seconds(), &account)?;
+    let released =
+        !released_amount(deps.storage, env.block.time.seconds(), account.grant_id)?.is_zero();
+    if let (true, Denom::Native(denom)) = (released && !return_amount.is_zero(), &denom) {
+        assert_released_covered(deps.branch(), &env, denom, return_amount)?;
+    }
+
+    account.pending_return_amount = account.pending_return_amount.checked_sub(return_amount)?;
+    if account.claimed_amount == account.vesting_amount && account.pending_return_amount.is_zero() {
+        remove_vesting_account(deps.storage, account.grant_id, env.block.height)?;
+    } else {
+        save_vesting_account(deps.storage, &account, env.block.height)?;
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !return_amount.is_zero() {
+        messages.push(transfer_message(
+            denom.clone(),
+            account.return_address.clone().unwrap(),
+            return_amount,
+        )?);
+    }
+
+    DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+        let mut summary = summary.unwrap_or_default();
+        summary.returned_amount = summary.returned_amount.checked_add(return_amount)?;
+        Ok(summary)
+    })?;
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", "terminate_vesting"),
+        ("address", address.as_str()),
+        ("grant_id", &account.grant_id.to_string()),
+        ("vesting_denom", &to_string(&denom).unwrap()),
+        ("vested_amount", &account.vesting_amount.to_string()),
+        ("left_vesting_amount", &return_amount.to_string()),
+        (
+            "pending_return_amount",
+            &account.pending_return_amount.to_string(),
+        ),
+    ]))
+}
+
 fn claim(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
     denoms: Vec<Denom>,
+    grant_ids: Option<Vec<u64>>,
     recipient: Option<String>,
-) -> StdResult<Response> {
+    msg: Option<Binary>,
+) -> Result<Response, ContractError> {
     let sender = info.sender;
+    if msg.is_some() && recipient.is_none() {
+        return Err(ContractError::MissingRecipient {});
+    }
+
     let recipient = recipient.unwrap_or_else(|| sender.to_string());
+    claim_vesting_accounts(
+        deps,
+        env,
+        sender.to_string(),
+        denoms,
+        grant_ids,
+        recipient,
+        msg,
+    )
+}
+
+fn claim_for(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    denoms: Vec<Denom>,
+) -> Result<Response, ContractError> {
+    let operator = OPERATORS
+        .may_load(deps.storage, (address.as_str(), info.sender.as_str()))?
+        .ok_or(ContractError::Unauthorized {})?;
+    if operator.expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+
+    let recipient = operator.recipient.unwrap_or_else(|| address.to_string());
+    claim_vesting_accounts(deps, env, address, denoms, None, recipient, None)
+}
 
+fn claim_vesting_accounts(
+    mut deps: DepsMut,
+    env: Env,
+    sender: String,
+    denoms: Vec<Denom>,
+    grant_ids: Option<Vec<u64>>,
+    recipient: String,
+    msg: Option<Binary>,
+) -> Result<Response, ContractError> {
     let mut messages: Vec<CosmosMsg> = vec![];
     let mut attrs: Vec<Attribute> = vec![];
     for denom in denoms.iter() {
         let denom_key = denom_to_key(denom.clone());
 
         // vesting_account existence check
-        let account = VESTING_ACCOUNTS.may_load(deps.storage, (sender.as_str(), &denom_key))?;
-        if account.is_none() {
-            return Err(StdError::generic_err(format!(
-                "vesting entry is not found for denom {}",
-                to_string(&denom).unwrap(),
-            )));
+        let accounts: Vec<VestingAccount> = grants_of(deps.storage, &sender, denom)?
+            .into_iter()
+            .filter(|account| match &grant_ids {
+                Some(grant_ids) => grant_ids.contains(&account.grant_id),
+                None => true,
+            })
+            .collect();
+        if accounts.is_empty() {
+            return Err(ContractError::NotFound {
+                denom: to_string(&denom).unwrap(),
+            });
         }
 
-        let mut account = account.unwrap();
-        let vested_amount = account
-            .vesting_schedule
-            .vested_amount(env.block.time.seconds())?;
-        let claimed_amount = account.claimed_amount;
+        // the grants of a denom are paid out together
+        let mut vesting_amount = Uint128::zero();
+        let mut vested_amount = Uint128::zero();
+        let mut claimable_amount = Uint128::zero();
+        let mut released = false;
+        let mut claimed_accounts: Vec<VestingAccount> = vec![];
+        for mut account in accounts.into_iter() {
+            let grant_vested_amount = account.vested_amount_at(&env.block)?;
+            vesting_amount = vesting_amount.checked_add(account.vesting_amount)?;
+            vested_amount = vested_amount.checked_add(grant_vested_amount)?;
+            released |= !released_amount(deps.storage, env.block.time.seconds(), account.grant_id)?
+                .is_zero();
+
+            // bonded and unbonding tokens are claimable after the unbonding
+            let unstaked_amount =
+                unstaked_amount(deps.storage, env.block.time.seconds(), &account)?;
+            let grant_claimable_amount = grant_vested_amount
+                .checked_sub(account.claimed_amount)?
+                .min(unstaked_amount);
+            if grant_claimable_amount.is_zero() {
+                continue;
+            }
+
+            account.claimed_amount = account.claimed_amount.checked_add(grant_claimable_amount)?;
+            claimed_accounts.push(account);
+
+            claimable_amount = claimable_amount.checked_add(grant_claimable_amount)?;
+        }
 
-        let claimable_amount = vested_amount.checked_sub(claimed_amount)?;
         if claimable_amount.is_zero() {
             continue;
         }
 
-        account.claimed_amount = vested_amount;
-        if account.claimed_amount == account.vesting_amount {
-            VESTING_ACCOUNTS.remove(deps.storage, (sender.as_str(), &denom_key));
-        } else {
-            VESTING_ACCOUNTS.save(deps.storage, (sender.as_str(), &denom_key), &account)?;
+        // the released unbondings are only paid when the staking module has
+        // returned them in full, so they cannot be paid out of the other grants
+        if let (true, Denom::Native(denom)) = (released, denom) {
+            assert_released_covered(deps.branch(), &env, denom, claimable_amount)?;
+        }
+
+        for account in claimed_accounts.iter() {
+            if account.claimed_amount == account.vesting_amount
+                && account.pending_return_amount.is_zero()
+            {
+                remove_vesting_account(deps.storage, account.grant_id, env.block.height)?;
+            } else {
+                save_vesting_account(deps.storage, account, env.block.height)?;
+            }
         }
 
-        let message: CosmosMsg = match account.vesting_denom.clone() {
-            Denom::Native(denom) => BankMsg::Send {
+        DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+            let mut summary = summary.unwrap_or_default();
+            summary.claimed_amount = summary.claimed_amount.checked_add(claimable_amount)?;
+            Ok(summary)
+        })?;
+
+        let message: CosmosMsg = match (denom.clone(), msg.clone()) {
+            (Denom::Native(denom), None) => BankMsg::Send {
                 to_address: recipient.clone(),
                 amount: vec![Coin {
                     denom,
@@ -349,7 +728,16 @@ fn claim(
                 }],
             }
             .into(),
-            Denom::Cw20(contract_addr) => WasmMsg::Execute {
+            (Denom::Native(denom), Some(msg)) => WasmMsg::Execute {
+                contract_addr: recipient.clone(),
+                msg,
+                funds: vec![Coin {
+                    denom,
+                    amount: claimable_amount,
+                }],
+            }
+            .into(),
+            (Denom::Cw20(contract_addr), None) => WasmMsg::Execute {
                 contract_addr: contract_addr.to_string(),
                 msg: to_binary(&Cw20ExecuteMsg::Transfer {
                     recipient: recipient.clone(),
@@ -358,13 +746,23 @@ fn claim(
                 funds: vec![],
             }
             .into(),
+            (Denom::Cw20(contract_addr), Some(msg)) => WasmMsg::Execute {
+                contract_addr: contract_addr.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: recipient.clone(),
+                    amount: claimable_amount,
+                    msg,
+                })?,
+                funds: vec![],
+            }
+            .into(),
         };
 
         messages.push(message);
         attrs.extend(
             vec![
-                Attribute::new("vesting_denom", &to_string(&account.vesting_denom).unwrap()),
-                Attribute::new("vesting_amount", &account.vesting_amount.to_string()),
+                Attribute::new("vesting_denom", &to_string(&denom).unwrap()),
+                Attribute::new("vesting_amount", &vesting_amount.to_string()),
                 Attribute::new("vested_amount", &vested_amount.to_string()),
                 Attribute::new("claim_amount", &claimable_amount.to_string()),
             ]
@@ -378,45 +776,574 @@ fn claim(
         .add_attributes(attrs))
 }
 
-pub fn receive_cw20(
+fn authorize_operator(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    cw20_msg: Cw20ReceiveMsg,
-) -> StdResult<Response> {
-    let amount = cw20_msg.amount;
-    let _sender = cw20_msg.sender;
-    let contract = info.sender;
+    operator: String,
+    recipient: Option<String>,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    let operator = deps.api.addr_validate(&operator)?.to_string();
+    let recipient = recipient
+        .map(|recipient| {
+            deps.api
+                .addr_validate(&recipient)
+                .map(|addr| addr.to_string())
+        })
+        .transpose()?;
+    if operator == info.sender {
+        return Err(ContractError::CannotSetOwnAccount {});
+    }
 
-    match from_binary(&cw20_msg.msg) {
-        Ok(Cw20HookMsg::RegisterVestingAccount {
-            master_address,
-            address,
-            vesting_schedule,
-        }) => register_vesting_account(
-            deps,
-            env,
-            master_address,
-            address,
-            Denom::Cw20(contract),
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::Expired {});
+    }
+
+    OPERATORS.save(
+        deps.storage,
+        (info.sender.as_str(), operator.as_str()),
+        &Operator {
+            recipient: recipient.clone(),
+            expires,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "authorize_operator"),
+        ("address", info.sender.as_str()),
+        ("operator", operator.as_str()),
+        ("recipient", recipient.unwrap_or_default().as_str()),
+        ("expires", &expires.to_string()),
+    ]))
+}
+
+fn revoke_operator(
+    deps: DepsMut,
+    info: MessageInfo,
+    operator: String,
+) -> Result<Response, ContractError> {
+    OPERATORS.remove(deps.storage, (info.sender.as_str(), operator.as_str()));
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "revoke_operator"),
+        ("address", info.sender.as_str()),
+        ("operator", operator.as_str()),
+    ]))
+}
+
+fn transfer_vesting_account(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: Denom,
+    grant_id: Option<u64>,
+    new_address: String,
+) -> Result<Response, ContractError> {
+    let sender = info.sender;
+
+    // vesting_account existence check
+    let account = load_grant(deps.storage, sender.as_str(), &denom, grant_id)?;
+
+    let new_address = deps.api.addr_validate(&new_address)?.to_string();
+    if new_address == sender {
+        return Err(ContractError::CannotSetOwnAccount {});
+    }
+
+    // wait for the master approval
+    if account.master_address.is_some() {
+        PENDING_TRANSFERS.save(deps.storage, U64Key::new(account.grant_id), &new_address)?;
+
+        return Ok(Response::new().add_attributes(vec![
+            ("action", "request_vesting_account_transfer"),
+            ("address", sender.as_str()),
+            ("grant_id", &account.grant_id.to_string()),
+            ("new_address", new_address.as_str()),
+            ("vesting_denom", &to_string(&denom).unwrap()),
+        ]));
+    }
+
+    move_vesting_account(deps, env, account, new_address)
+}
+
+fn approve_vesting_account_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    // vesting_account existence check
+    let account = load_grant(deps.storage, &address, &denom, grant_id)?;
+    if account.master_address.is_none() || account.master_address.clone().unwrap() != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let new_address = PENDING_TRANSFERS
+        .may_load(deps.storage, U64Key::new(account.grant_id))?
+        .ok_or(ContractError::NoPendingTransfer {})?;
+
+    move_vesting_account(deps, env, account, new_address)
+}
+
+fn move_vesting_account(
+    deps: DepsMut,
+    env: Env,
+    mut account: VestingAccount,
+    new_address: String,
+) -> Result<Response, ContractError> {
+    // the rewards accrued by the delegations are only settled on the next staking action
+    // and would be paid to the new address, so the staked tokens must be released first
+    if !staked_amount(deps.storage, env.block.time.seconds(), account.grant_id)?.is_zero() {
+        return Err(ContractError::StakedFunds {});
+    }
+
+    // the grant keeps its grant_id and pending master proposal at the new address
+    let grant_key = U64Key::new(account.grant_id);
+    let pending_master = PENDING_MASTERS.may_load(deps.storage, grant_key.clone())?;
+    remove_vesting_account(deps.storage, account.grant_id, env.block.height)?;
+    let address = account.address;
+    account.address = new_address.to_string();
+    save_vesting_account(deps.storage, &account, env.block.height)?;
+    if let Some(new_master_address) = pending_master {
+        PENDING_MASTERS.save(deps.storage, grant_key, &new_master_address)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "transfer_vesting_account"),
+        ("address", address.as_str()),
+        ("grant_id", &account.grant_id.to_string()),
+        ("new_address", new_address.as_str()),
+        ("vesting_denom", &to_string(&account.vesting_denom).unwrap()),
+    ]))
+}
+
+fn propose_new_master(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+    new_master_address: String,
+) -> Result<Response, ContractError> {
+    // vesting_account existence check
+    let account = load_grant(deps.storage, &address, &denom, grant_id)?;
+    if account.master_address.is_none() || account.master_address.clone().unwrap() != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let new_master_address = deps.api.addr_validate(&new_master_address)?.to_string();
+    PENDING_MASTERS.save(
+        deps.storage,
+        U64Key::new(account.grant_id),
+        &new_master_address,
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "propose_new_master"),
+        ("address", address.as_str()),
+        ("grant_id", &account.grant_id.to_string()),
+        ("vesting_denom", &to_string(&denom).unwrap()),
+        ("new_master_address", new_master_address.as_str()),
+    ]))
+}
+
+fn accept_master(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut account = load_grant(deps.storage, &address, &denom, grant_id)?;
+    let grant_key = U64Key::new(account.grant_id);
+
+    let new_master_address = PENDING_MASTERS
+        .may_load(deps.storage, grant_key.clone())?
+        .ok_or(ContractError::NoPendingMaster {})?;
+    if new_master_address != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    account.master_address = Some(new_master_address.to_string());
+    vesting_accounts().save(deps.storage, grant_key.clone(), &account)?;
+    PENDING_MASTERS.remove(deps.storage, grant_key);
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "accept_master"),
+        ("address", address.as_str()),
+        ("grant_id", &account.grant_id.to_string()),
+        ("vesting_denom", &to_string(&denom).unwrap()),
+        ("master_address", new_master_address.as_str()),
+    ]))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn amend_vesting_account(
+    deps: DepsMut,
+    env: Env,
+    sender: String,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+    end_time: Option<Uint64>,
+    top_up_amount: Uint128,
+) -> Result<Response, ContractError> {
+    let denom_key = denom_to_key(denom.clone());
+
+    // vesting_account existence check
+    let mut account = load_grant(deps.storage, &address, &denom, grant_id)?;
+    if account.master_address.is_none() || account.master_address.clone().unwrap() != sender {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    if account.terminated_at.is_some() {
+        return Err(ContractError::AlreadyTerminated {});
+    }
+
+    // slashes lower the vesting_amount below the schedule total,
+    // so the deposit is compared with the growth of the schedule itself
+    let vesting_schedule = account.vesting_schedule.amend(end_time, top_up_amount)?;
+    let expected_top_up_amount = vesting_schedule
+        .total_amount()?
+        .checked_sub(account.vesting_schedule.total_amount()?)?;
+    if expected_top_up_amount != top_up_amount {
+        return Err(ContractError::DepositMismatch {
+            expected: expected_top_up_amount,
+            got: top_up_amount,
+        });
+    }
+    let vesting_amount = account.vesting_amount.checked_add(top_up_amount)?;
+
+    // amendment cannot take back the already claimed amount
+    if vesting_schedule.vested_amount_at(&env.block)? < account.claimed_amount {
+        return Err(ContractError::invalid_schedule(
+            "assert(vested_amount >= claimed_amount)",
+        ));
+    }
+
+    account.vesting_schedule = vesting_schedule;
+    account.vesting_amount = vesting_amount;
+    save_vesting_account(deps.storage, &account, env.block.height)?;
+
+    DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+        let mut summary = summary.unwrap_or_default();
+        summary.deposited_amount = summary.deposited_amount.checked_add(top_up_amount)?;
+        Ok(summary)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "amend_vesting_account"),
+        ("address", address.as_str()),
+        ("grant_id", &account.grant_id.to_string()),
+        ("vesting_denom", &to_string(&denom).unwrap()),
+        ("vesting_amount", &vesting_amount.to_string()),
+        ("top_up_amount", &top_up_amount.to_string()),
+    ]))
+}
+
+fn set_paused(deps: DepsMut, info: MessageInfo, paused: bool) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    config.paused = paused;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", if paused { "pause" } else { "unpause" }))
+}
+
+fn update_allowed_denoms(
+    deps: DepsMut,
+    info: MessageInfo,
+    allowed_denoms: Option<Vec<Denom>>,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    config.allowed_denoms = allowed_denoms;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_allowed_denoms"),
+        (
+            "allowed_denoms",
+            &to_string(&config.allowed_denoms).unwrap(),
+        ),
+    ]))
+}
+
+fn update_admin(
+    deps: DepsMut,
+    info: MessageInfo,
+    admin: String,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    config.admin = Some(deps.api.addr_validate(&admin)?.to_string());
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![("action", "update_admin"), ("admin", &admin)]))
+}
+
+fn update_unbonding_period(
+    deps: DepsMut,
+    info: MessageInfo,
+    unbonding_period: u64,
+) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    assert_admin(&config, &info)?;
+
+    if unbonding_period == 0 {
+        return Err(ContractError::InvalidUnbondingPeriod {});
+    }
+
+    // the unbondings in progress keep their release_time
+    config.unbonding_period = unbonding_period;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_unbonding_period"),
+        ("unbonding_period", &unbonding_period.to_string()),
+    ]))
+}
+
+fn assert_admin(config: &Config, info: &MessageInfo) -> Result<(), ContractError> {
+    match &config.admin {
+        Some(admin) if *admin == info.sender => Ok(()),
+        _ => Err(ContractError::Unauthorized {}),
+    }
+}
+
+fn transfer_message(denom: Denom, recipient: String, amount: Uint128) -> StdResult<CosmosMsg> {
+    Ok(match denom {
+        Denom::Native(denom) => BankMsg::Send {
+            to_address: recipient,
+            amount: vec![Coin { denom, amount }],
+        }
+        .into(),
+        Denom::Cw20(contract_addr) => WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer { recipient, amount })?,
+            funds: vec![],
+        }
+        .into(),
+    })
+}
+
+fn native_denom(denom: &Denom) -> Result<String, ContractError> {
+    match denom {
+        Denom::Native(denom) => Ok(denom.to_string()),
+        Denom::Cw20(_) => Err(ContractError::Cw20DepositRequired {}),
+    }
+}
+
+pub fn receive_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let amount = cw20_msg.amount;
+    let sender = cw20_msg.sender;
+    let contract = info.sender;
+
+    match from_binary(&cw20_msg.msg) {
+        Ok(Cw20HookMsg::RegisterVestingAccount {
+            master_address,
+            address,
+            vesting_schedule,
+        }) => register_vesting_account(
+            deps,
+            env,
+            master_address,
+            address,
+            Denom::Cw20(contract),
             amount,
             vesting_schedule,
         ),
-        Err(_) => Err(StdError::generic_err("invalid cw20 hook message")),
+        Ok(Cw20HookMsg::RegisterVestingAccounts { vesting_accounts }) => {
+            register_vesting_accounts(deps, env, Denom::Cw20(contract), amount, vesting_accounts)
+        }
+        Ok(Cw20HookMsg::AmendVestingAccount {
+            address,
+            grant_id,
+            end_time,
+        }) => amend_vesting_account(
+            deps,
+            env,
+            sender,
+            address,
+            Denom::Cw20(contract),
+            grant_id,
+            end_time,
+            amount,
+        ),
+        Err(_) => Err(ContractError::InvalidCw20HookMsg {}),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let admin = msg
+        .admin
+        .map(|admin| deps.api.addr_validate(&admin))
+        .transpose()?;
+
+    // the versions are checked when the storage migration starts,
+    // the later calls resume it
+    if MIGRATION.may_load(deps.storage)?.is_none() {
+        // contracts deployed before versioning have no contract info
+        let previous_version = match CONTRACT.may_load(deps.storage)? {
+            Some(info) if info.contract != CONTRACT_NAME => {
+                return Err(ContractError::CannotMigrate {
+                    previous_contract: info.contract,
+                })
+            }
+            Some(info) => info.version,
+            None => "0.0.0".to_string(),
+        };
+
+        if parse_version(&previous_version)? > parse_version(CONTRACT_VERSION)? {
+            return Err(ContractError::CannotDowngrade { previous_version });
+        }
+
+        // vesting accounts were keyed by (address, denom) before 0.2.0
+        let legacy = parse_version(&previous_version)? < Version::new(0, 2, 0);
+        start_migration(deps.storage, previous_version, legacy)?;
+    }
+
+    let mut config = CONFIG.may_load(deps.storage)?.unwrap_or(Config {
+        unbonding_period: DEFAULT_UNBONDING_PERIOD,
+        admin: None,
+        paused: false,
+        allowed_denoms: None,
+    });
+    if let Some(admin) = admin {
+        config.admin = Some(admin.to_string());
     }
+    CONFIG.save(deps.storage, &config)?;
+
+    let migration = migrate_storage(
+        deps.storage,
+        env.block.height,
+        msg.limit.unwrap_or(DEFAULT_MIGRATION_LIMIT),
+    )?;
+    if migration.completed {
+        set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "migrate"),
+        ("previous_version", migration.previous_version.as_str()),
+        ("version", CONTRACT_VERSION),
+        ("migrated_accounts", &migration.accounts.to_string()),
+        ("completed", &migration.completed.to_string()),
+    ]))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        WITHDRAW_REWARDS_REPLY_ID => withdraw_rewards_reply(deps, env),
+        id => Err(ContractError::UnknownReplyId { id }),
+    }
+}
+
+fn parse_version(version: &str) -> StdResult<Version> {
+    Version::parse(version).map_err(|err| StdError::generic_err(err.to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    assert_not_migrating(deps.storage).map_err(|err| StdError::generic_err(err.to_string()))?;
+
     match msg {
+        QueryMsg::Config {} => to_binary(&query_config(deps)?),
         QueryMsg::VestingAccount {
+            address,
+            start_after,
+            start_after_grant,
+            limit,
+        } => to_binary(&vesting_account(
+            deps,
+            env,
+            address,
+            start_after,
+            start_after_grant,
+            limit,
+        )?),
+        QueryMsg::VestingSummary { address, denom } => {
+            to_binary(&vesting_summary(deps, env, address, denom)?)
+        }
+        QueryMsg::VestingAccounts { start_after, limit } => {
+            to_binary(&all_vesting_accounts(deps, env, start_after, limit)?)
+        }
+        QueryMsg::VestingAccountsByDenom {
+            denom,
+            start_after,
+            limit,
+        } => to_binary(&vesting_accounts_by_denom(
+            deps,
+            env,
+            denom,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::VestingAccountsByMaster {
+            master_address,
+            start_after,
+            limit,
+        } => to_binary(&vesting_accounts_by_master(
+            deps,
+            env,
+            master_address,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::DenomSummary { denom } => to_binary(&denom_summary(deps, env, denom)?),
+        QueryMsg::Operators {
             address,
             start_after,
             limit,
-        } => to_binary(&vesting_account(deps, env, address, start_after, limit)?),
+        } => to_binary(&operators(deps, address, start_after, limit)?),
+        QueryMsg::SimulateVesting {
+            address,
+            denom,
+            grant_id,
+            at_time,
+            at_height,
+        } => to_binary(&simulate_vesting(
+            deps, env, address, denom, grant_id, at_time, at_height,
+        )?),
+        QueryMsg::PreviewSchedule { schedule, at_times } => {
+            to_binary(&preview_schedule(schedule, at_times)?)
+        }
+        QueryMsg::Stake {
+            address,
+            denom,
+            grant_id,
+        } => to_binary(&query_stake(deps, env, address, denom, grant_id)?),
+        QueryMsg::VotingPower {
+            address,
+            denom,
+            height,
+        } => to_binary(&voting_power(deps, env, address, denom, height)?),
     }
 }
 
+fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    Ok(ConfigResponse {
+        admin: config.admin,
+        unbonding_period: config.unbonding_period,
+        paused: config.paused,
+        allowed_denoms: config.allowed_denoms,
+    })
+}
+
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
 fn vesting_account(
@@ -424,38 +1351,327 @@ fn vesting_account(
     env: Env,
     address: String,
     start_after: Option<Denom>,
+    start_after_grant: Option<u64>,
     limit: Option<u32>,
 ) -> StdResult<VestingAccountResponse> {
     let mut vestings: Vec<VestingData> = vec![];
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
 
-    for item in VESTING_ACCOUNTS
-        .prefix(address.as_str())
+    for item in vesting_accounts()
+        .idx
+        .address
+        .sub_prefix(address.as_bytes().to_vec())
         .range(
             deps.storage,
-            start_after
-                .map(denom_to_key)
-                .map(|v| v.as_bytes().to_vec())
-                .map(Bound::Exclusive),
+            start_after.map(|denom| {
+                // a denom alone skips all of its grants
+                let denom_key = denom_to_key(denom);
+                let grant_id = start_after_grant.unwrap_or(u64::MAX);
+                Bound::Exclusive((denom_key.as_bytes(), U64Key::new(grant_id)).joined_key())
+            }),
             None,
             Order::Ascending,
         )
         .take(limit)
     {
         let (_, account) = item?;
-        let vested_amount = account
-            .vesting_schedule
-            .vested_amount(env.block.time.seconds())?;
-
-        vestings.push(VestingData {
-            master_address: account.master_address,
-            vesting_denom: account.vesting_denom,
-            vesting_amount: account.vesting_amount,
+        vestings.push(to_vesting_data(deps.storage, &env, account)?);
+    }
+
+    Ok(VestingAccountResponse { address, vestings })
+}
+
+fn vesting_summary(
+    deps: Deps,
+    env: Env,
+    address: String,
+    denom: Denom,
+) -> StdResult<VestingSummaryResponse> {
+    let mut grant_ids: Vec<u64> = vec![];
+    let mut vesting_amount = Uint128::zero();
+    let mut vested_amount = Uint128::zero();
+    let mut claimable_amount = Uint128::zero();
+    for account in grants_of(deps.storage, &address, &denom)? {
+        let vesting = to_vesting_data(deps.storage, &env, account)?;
+        grant_ids.push(vesting.grant_id);
+        vesting_amount = vesting_amount.checked_add(vesting.vesting_amount)?;
+        vested_amount = vested_amount.checked_add(vesting.vested_amount)?;
+        claimable_amount = claimable_amount.checked_add(vesting.claimable_amount)?;
+    }
+
+    Ok(VestingSummaryResponse {
+        address,
+        vesting_denom: denom,
+        grant_ids,
+        vesting_amount,
+        vested_amount,
+        claimable_amount,
+    })
+}
+
+fn all_vesting_accounts(
+    deps: Deps,
+    env: Env,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<VestingAccountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let vesting_accounts = vesting_accounts()
+        .range(
+            deps.storage,
+            start_after.map(to_grant_bound),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| to_vesting_account_data(deps.storage, &env, item?.1))
+        .collect::<StdResult<Vec<VestingAccountData>>>()?;
+
+    Ok(VestingAccountsResponse { vesting_accounts })
+}
+
+fn vesting_accounts_by_denom(
+    deps: Deps,
+    env: Env,
+    denom: Denom,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<VestingAccountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let vesting_accounts = vesting_accounts()
+        .idx
+        .denom
+        .prefix(denom_to_key(denom).into_bytes())
+        .range(
+            deps.storage,
+            start_after.map(to_grant_bound),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| to_vesting_account_data(deps.storage, &env, item?.1))
+        .collect::<StdResult<Vec<VestingAccountData>>>()?;
+
+    Ok(VestingAccountsResponse { vesting_accounts })
+}
+
+fn vesting_accounts_by_master(
+    deps: Deps,
+    env: Env,
+    master_address: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<VestingAccountsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let vesting_accounts = vesting_accounts()
+        .idx
+        .master
+        .prefix(master_address.into_bytes())
+        .range(
+            deps.storage,
+            start_after.map(to_grant_bound),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| to_vesting_account_data(deps.storage, &env, item?.1))
+        .collect::<StdResult<Vec<VestingAccountData>>>()?;
+
+    Ok(VestingAccountsResponse { vesting_accounts })
+}
+
+fn denom_summary(deps: Deps, env: Env, denom: Denom) -> StdResult<DenomSummaryResponse> {
+    let denom_key = denom_to_key(denom.clone());
+    let summary = DENOM_SUMMARIES
+        .may_load(deps.storage, &denom_key)?
+        .unwrap_or_default();
+
+    // locked amount is the unvested amount of all remaining vesting accounts,
+    // it depends on the block time so it can't be kept as a running total
+    let mut locked_amount = Uint128::zero();
+    for item in vesting_accounts()
+        .idx
+        .denom
+        .prefix(denom_key.into_bytes())
+        .range(deps.storage, None, None, Order::Ascending)
+    {
+        let (_, account) = item?;
+        let vested_amount = account.vested_amount_at(&env.block)?;
+        locked_amount =
+            locked_amount.checked_add(account.vesting_amount.checked_sub(vested_amount)?)?;
+    }
+
+    Ok(DenomSummaryResponse {
+        denom,
+        deposited_amount: summary.deposited_amount,
+        claimed_amount: summary.claimed_amount,
+        returned_amount: summary.returned_amount,
+        slashed_amount: summary.slashed_amount,
+        unclaimed_amount: summary.unclaimed_amount,
+        locked_amount,
+    })
+}
+
+fn operators(
+    deps: Deps,
+    address: String,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OperatorsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+
+    let operators = OPERATORS
+        .prefix(address.as_str())
+        .range(
+            deps.storage,
+            start_after.map(|v| Bound::Exclusive(v.into_bytes())),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| {
+            let (operator, info) = item?;
+            Ok(OperatorInfo {
+                operator: String::from_utf8(operator)?,
+                recipient: info.recipient,
+                expires: info.expires,
+            })
+        })
+        .collect::<StdResult<Vec<OperatorInfo>>>()?;
+
+    Ok(OperatorsResponse { operators })
+}
+
+fn to_grant_bound(grant_id: u64) -> Bound {
+    Bound::Exclusive(U64Key::new(grant_id).joined_key())
+}
+
+fn to_vesting_account_data(
+    storage: &dyn Storage,
+    env: &Env,
+    account: VestingAccount,
+) -> StdResult<VestingAccountData> {
+    Ok(VestingAccountData {
+        address: account.address.clone(),
+        vesting: to_vesting_data(storage, env, account)?,
+    })
+}
+
+fn to_vesting_data(
+    storage: &dyn Storage,
+    env: &Env,
+    account: VestingAccount,
+) -> StdResult<VestingData> {
+    let vested_amount = account.vested_amount_at(&env.block)?;
+    let unstaked_amount = unstaked_amount(storage, env.block.time.seconds(), &account)?;
+
+    Ok(VestingData {
+        grant_id: account.grant_id,
+        master_address: account.master_address,
+        vesting_denom: account.vesting_denom,
+        vesting_amount: account.vesting_amount,
+        vested_amount,
+        vesting_schedule: account.vesting_schedule,
+        claimable_amount: vested_amount
+            .checked_sub(account.claimed_amount)?
+            .min(unstaked_amount),
+    })
+}
+
+fn simulate_vesting(
+    deps: Deps,
+    env: Env,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+    at_time: u64,
+    at_height: Option<u64>,
+) -> StdResult<VestingSimulationResponse> {
+    let accounts = load_grants(deps.storage, &address, &denom, grant_id)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+
+    // each schedule is evaluated in its own unit
+    let block = BlockInfo {
+        height: at_height.unwrap_or(env.block.height),
+        time: Timestamp::from_seconds(at_time),
+        chain_id: env.block.chain_id,
+    };
+
+    let mut vesting_amount = Uint128::zero();
+    let mut vested_amount = Uint128::zero();
+    let mut claimable_amount = Uint128::zero();
+    for account in accounts.iter() {
+        let grant_vested_amount = account.vested_amount_at(&block)?;
+        vesting_amount = vesting_amount.checked_add(account.vesting_amount)?;
+        vested_amount = vested_amount.checked_add(grant_vested_amount)?;
+        // nothing can be claimed at the times before the last claim, and the staked
+        // tokens only once released at at_time, as claimed by to_vesting_data
+        let unstaked_amount = unstaked_amount(deps.storage, at_time, account)?;
+        claimable_amount = claimable_amount.checked_add(
+            grant_vested_amount
+                .saturating_sub(account.claimed_amount)
+                .min(unstaked_amount),
+        )?;
+    }
+
+    Ok(VestingSimulationResponse {
+        vesting_amount,
+        simulations: vec![VestingSimulation {
+            at_time,
             vested_amount,
-            vesting_schedule: account.vesting_schedule,
-            claimable_amount: vested_amount.checked_sub(account.claimed_amount)?,
+            claimable_amount,
+        }],
+    })
+}
+
+fn preview_schedule(
+    schedule: VestingSchedule,
+    at_times: Vec<u64>,
+) -> StdResult<VestingSimulationResponse> {
+    // the block height based schedules take the heights in at_times
+    let simulations = at_times
+        .into_iter()
+        .map(|at_time| {
+            let vested_amount = schedule.vested_amount(at_time)?;
+            Ok(VestingSimulation {
+                at_time,
+                vested_amount,
+                claimable_amount: vested_amount,
+            })
         })
+        .collect::<StdResult<Vec<VestingSimulation>>>()?;
+
+    Ok(VestingSimulationResponse {
+        vesting_amount: schedule.total_amount()?,
+        simulations,
+    })
+}
+
+fn voting_power(
+    deps: Deps,
+    env: Env,
+    address: String,
+    denom: Denom,
+    height: Option<u64>,
+) -> StdResult<VotingPowerResponse> {
+    let denom_key = denom_to_key(denom.clone());
+    let voting_power = match height {
+        Some(height) => VOTING_POWERS.may_load_at_height(
+            deps.storage,
+            (address.as_str(), &denom_key),
+            height,
+        )?,
+        None => VOTING_POWERS.may_load(deps.storage, (address.as_str(), &denom_key))?,
     }
+    .unwrap_or_default();
 
-    Ok(VestingAccountResponse { address, vestings })
+    Ok(VotingPowerResponse {
+        address,
+        denom,
+        height: height.unwrap_or(env.block.height),
+        voting_power,
+    })
 }
@@ -0,0 +1,100 @@
+use cosmwasm_std::{OverflowError, StdError, Uint128};
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ContractError {
+    #[error("{0}")]
+    Std(#[from] StdError),
+
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Vesting entry is not found for denom {denom}")]
+    NotFound { denom: String },
+
+    #[error("Multiple grants exist for the denom; grant_id is required")]
+    GrantIdRequired {},
+
+    #[error("Contract is paused")]
+    Paused {},
+
+    #[error("Denom {denom} is not allowed")]
+    DenomNotAllowed { denom: String },
+
+    #[error("Vesting is already terminated")]
+    AlreadyTerminated {},
+
+    #[error("Invalid vesting schedule: {reason}")]
+    InvalidSchedule { reason: String },
+
+    #[error("Deposit amount mismatch: expected {expected}, got {got}")]
+    DepositMismatch { expected: Uint128, got: Uint128 },
+
+    #[error("Must deposit only one type of vesting token")]
+    InvalidDeposit {},
+
+    #[error("Cw20 token must be deposited via cw20 hook message")]
+    Cw20DepositRequired {},
+
+    #[error("Invalid cw20 hook message")]
+    InvalidCw20HookMsg {},
+
+    #[error("No vesting accounts to register")]
+    EmptyVestingAccounts {},
+
+    #[error("Cannot set to own account")]
+    CannotSetOwnAccount {},
+
+    #[error("Recipient contract must be given with msg")]
+    MissingRecipient {},
+
+    #[error("Operator authorization is expired")]
+    Expired {},
+
+    #[error("No pending transfer")]
+    NoPendingTransfer {},
+
+    #[error("No pending master")]
+    NoPendingMaster {},
+
+    #[error("Invalid zero amount")]
+    InvalidZeroAmount {},
+
+    #[error("Only the bonded native token can be staked")]
+    NotStakable {},
+
+    #[error("Insufficient unstaked funds: {available} available")]
+    InsufficientFunds { available: Uint128 },
+
+    #[error("Vesting account has bonded or unbonding funds")]
+    StakedFunds {},
+
+    #[error("Unbonding period must be positive")]
+    InvalidUnbondingPeriod {},
+
+    #[error("No delegation to the validator")]
+    NoDelegation {},
+
+    #[error("Unknown reply id: {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("Storage migration is in progress")]
+    MigrationInProgress {},
+
+    #[error("Cannot migrate from different contract type: {previous_contract}")]
+    CannotMigrate { previous_contract: String },
+
+    #[error("Cannot migrate from newer version: {previous_version}")]
+    CannotDowngrade { previous_version: String },
+}
+
+impl ContractError {
+    pub fn invalid_schedule(reason: &str) -> Self {
+        ContractError::InvalidSchedule {
+            reason: reason.to_string(),
+        }
+    }
+}
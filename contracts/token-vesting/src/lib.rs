@@ -1,5 +1,8 @@
 pub mod contract;
+pub mod error;
+pub mod migration;
 pub mod msg;
+pub mod staking;
 pub mod state;
 
 #[cfg(test)]
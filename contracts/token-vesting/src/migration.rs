@@ -0,0 +1,177 @@
+use cosmwasm_std::{Order, StdResult, Storage, Uint128};
+use cw_storage_plus::{Bound, PrimaryKey, U64Key};
+
+use crate::error::ContractError;
+use crate::state::{
+    denom_to_key, legacy_vesting_accounts, next_grant_id, vesting_accounts, DenomSummary,
+    MigrationInfo, MigrationStage, VestingAccount, DENOM_SUMMARIES, MIGRATION,
+    STAGED_VOTING_POWERS, VOTING_POWERS,
+};
+
+/// Starts the storage migration from the given version, the vesting accounts
+/// of the versions before 0.2.0 are moved to grants first.
+pub fn start_migration(
+    storage: &mut dyn Storage,
+    previous_version: String,
+    legacy: bool,
+) -> StdResult<()> {
+    // the unclaimed amounts are summed again over the rewritten grants
+    let summaries = DENOM_SUMMARIES
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<(Vec<u8>, DenomSummary)>>>()?;
+    for (denom_key, mut summary) in summaries.into_iter() {
+        summary.unclaimed_amount = Uint128::zero();
+        DENOM_SUMMARIES.save(storage, &String::from_utf8(denom_key)?, &summary)?;
+    }
+
+    MIGRATION.save(
+        storage,
+        &MigrationInfo {
+            stage: if legacy {
+                MigrationStage::LegacyAccounts
+            } else {
+                MigrationStage::Grants
+            },
+            previous_version,
+            last_grant_id: None,
+            accounts: 0,
+            completed: false,
+        },
+    )
+}
+
+/// Processes up to `limit` entries of the started storage migration.
+/// The progress is removed once the migration completes.
+pub fn migrate_storage(
+    storage: &mut dyn Storage,
+    height: u64,
+    limit: u32,
+) -> Result<MigrationInfo, ContractError> {
+    let mut migration = MIGRATION.load(storage)?;
+
+    let mut remaining = limit as usize;
+    while remaining > 0 && !migration.completed {
+        let moved = match migration.stage {
+            MigrationStage::LegacyAccounts => migrate_legacy_vesting_accounts(storage, remaining)?,
+            MigrationStage::Grants => {
+                let moved = rewrite_grants(storage, &mut migration.last_grant_id, remaining)?;
+                migration.accounts += moved as u64;
+                moved
+            }
+            MigrationStage::VotingPowers => save_voting_powers(storage, height, remaining)?,
+        };
+
+        // the stage is drained once it can't fill the batch
+        if moved < remaining {
+            match migration.stage {
+                MigrationStage::LegacyAccounts => migration.stage = MigrationStage::Grants,
+                MigrationStage::Grants => migration.stage = MigrationStage::VotingPowers,
+                MigrationStage::VotingPowers => migration.completed = true,
+            }
+        }
+        remaining -= moved;
+    }
+
+    if migration.completed {
+        MIGRATION.remove(storage);
+    } else {
+        MIGRATION.save(storage, &migration)?;
+    }
+    Ok(migration)
+}
+
+/// Refuses to operate on the storage while it is migrated
+pub fn assert_not_migrating(storage: &dyn Storage) -> Result<(), ContractError> {
+    match MIGRATION.may_load(storage)? {
+        Some(_) => Err(ContractError::MigrationInProgress {}),
+        None => Ok(()),
+    }
+}
+
+/// move the (address, denom) keyed vesting accounts to new grants,
+/// the versions before 0.2.0 stored nothing else per account
+fn migrate_legacy_vesting_accounts(storage: &mut dyn Storage, limit: usize) -> StdResult<usize> {
+    let accounts = legacy_vesting_accounts()
+        .range(storage, None, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, account)| account))
+        .collect::<StdResult<Vec<VestingAccount>>>()?;
+
+    let migrated = accounts.len();
+    for mut account in accounts.into_iter() {
+        let address = account.address.to_string();
+        let denom_key = denom_to_key(account.vesting_denom.clone());
+        let legacy_key = (address.as_str(), denom_key.as_str());
+        legacy_vesting_accounts().remove(storage, legacy_key)?;
+
+        account.grant_id = next_grant_id(storage)?;
+        vesting_accounts().save(storage, U64Key::new(account.grant_id), &account)?;
+    }
+
+    Ok(migrated)
+}
+
+/// rewrite the grants after the last one in the current schema, which also builds
+/// the indexes missing from older versions, and sum their voting powers and unclaimed amounts
+fn rewrite_grants(
+    storage: &mut dyn Storage,
+    last_grant_id: &mut Option<u64>,
+    limit: usize,
+) -> StdResult<usize> {
+    let accounts = vesting_accounts()
+        .range(
+            storage,
+            last_grant_id.map(|grant_id| Bound::Exclusive(U64Key::new(grant_id).joined_key())),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, account)| account))
+        .collect::<StdResult<Vec<VestingAccount>>>()?;
+
+    for account in accounts.iter() {
+        vesting_accounts().save(storage, U64Key::new(account.grant_id), account)?;
+
+        let denom_key = denom_to_key(account.vesting_denom.clone());
+        let grant_power = account.vesting_amount.checked_sub(account.claimed_amount)?;
+        STAGED_VOTING_POWERS.update(
+            storage,
+            (account.address.as_str(), &denom_key),
+            |power| -> StdResult<_> { Ok(power.unwrap_or_default().checked_add(grant_power)?) },
+        )?;
+        DENOM_SUMMARIES.update(storage, &denom_key, |summary| -> StdResult<_> {
+            let mut summary = summary.unwrap_or_default();
+            summary.unclaimed_amount = summary
+                .unclaimed_amount
+                .checked_add(grant_power)?
+                .checked_add(account.pending_return_amount)?;
+            Ok(summary)
+        })?;
+        *last_grant_id = Some(account.grant_id);
+    }
+
+    Ok(accounts.len())
+}
+
+fn save_voting_powers(storage: &mut dyn Storage, height: u64, limit: usize) -> StdResult<usize> {
+    let voting_powers = STAGED_VOTING_POWERS
+        .range(storage, None, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<(Vec<u8>, Uint128)>>>()?;
+
+    for (key, voting_power) in voting_powers.iter() {
+        let (address, denom_key) = split_pair_key(key);
+        let address = String::from_utf8(address.to_vec())?;
+        let denom_key = String::from_utf8(denom_key.to_vec())?;
+        VOTING_POWERS.save(storage, (&address, &denom_key), voting_power, height)?;
+        STAGED_VOTING_POWERS.remove(storage, (&address, &denom_key));
+    }
+
+    Ok(voting_powers.len())
+}
+
+/// composite keys are returned with the length prefixed first part
+fn split_pair_key(key: &[u8]) -> (&[u8], &[u8]) {
+    let len = u16::from_be_bytes([key[0], key[1]]) as usize;
+    (&key[2..2 + len], &key[2 + len..])
+}
@@ -1,10 +1,29 @@
-use cosmwasm_std::{StdResult, Uint128};
-use cw20::{Cw20ReceiveMsg, Denom};
+use cosmwasm_std::{Binary, BlockInfo, StdError, StdResult, Uint128, Uint64};
+
+use crate::error::ContractError;
+use cw20::{Cw20ReceiveMsg, Denom, Expiration};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, JsonSchema)]
-pub struct InstantiateMsg {}
+pub struct InstantiateMsg {
+    /// unbonding period of the chain in second unit, defaults to 21 days
+    pub unbonding_period: Option<u64>,
+    /// admin of the pause and the denom allowlist, defaults to the sender
+    pub admin: Option<String>,
+    /// accepted vesting denoms, any denom is accepted when not given
+    pub allowed_denoms: Option<Vec<Denom>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {
+    /// if given, replaces the admin, which is not set
+    /// for the contracts instantiated before 0.2.0
+    pub admin: Option<String>,
+    /// number of entries processed by this call, defaults to 100.
+    /// The contract is not usable until the storage migration completes.
+    pub limit: Option<u32>,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
@@ -19,20 +38,149 @@ pub enum ExecuteMsg {
         address: String,
         vesting_schedule: VestingSchedule,
     },
-    /// only available when master_address was set
+    /// Register multiple vesting accounts with a single deposit.
+    /// The deposit must equal the sum of all vesting amounts.
+    RegisterVestingAccounts {
+        vesting_accounts: Vec<VestingAccountMsg>,
+    },
+    /// Register multiple vesting accounts of the cw20 token by pulling
+    /// the sum of all vesting amounts from the allowance given to this contract.
+    RegisterVestingAccountsFrom {
+        token: String,
+        vesting_accounts: Vec<VestingAccountMsg>,
+    },
+    /// only available when master_address was set,
+    /// all grants of the denom are deregistered when grant_id is not given
     DeregisterVestingAccount {
         address: String,
         denom: Denom,
+        grant_id: Option<u64>,
         vested_token_recipient: Option<String>,
         left_vesting_token_recipient: Option<String>,
     },
+    /// only available when master_address was set,
+    /// stop the vesting at the current vested amount and return the unvested amount.
+    /// The vested amount stays claimable by the vesting account.
+    /// The staked unvested amount is returned by terminating again once it is released.
+    TerminateVesting {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+        left_vesting_token_recipient: Option<String>,
+    },
+    /// only available when master_address was set,
+    /// approve the transfer requested by the vesting account
+    ApproveVestingAccountTransfer {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+    },
+    /// only available when master_address was set,
+    /// the new master must accept the proposal to take control
+    ProposeNewMaster {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+        new_master_address: String,
+    },
+    /// accept the proposal made by the current master
+    AcceptMaster {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+    },
+    /// only available when master_address was set,
+    /// extend end_time and top up the vesting amount with the deposit
+    AmendVestingAccount {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+        end_time: Option<Uint64>,
+    },
 
     ////////////////////////
     /// VestingAccount Operations ///
     ////////////////////////
+    /// The operations on a single grant take an optional grant_id,
+    /// which can be omitted when the address has only one grant of the denom.
+    ///
+    /// Claim all grants of the denoms, or only the given grant_ids.
+    /// When msg is given, the claimed tokens are sent to the recipient contract
+    /// with the msg, via cw20 Send or wasm Execute with funds for native tokens.
     Claim {
         denoms: Vec<Denom>,
+        grant_ids: Option<Vec<u64>>,
+        recipient: Option<String>,
+        msg: Option<Binary>,
+    },
+    /// Move the vesting account to the new_address.
+    /// When master_address was set, the transfer is executed
+    /// after the master approves it.
+    TransferVestingAccount {
+        denom: Denom,
+        grant_id: Option<u64>,
+        new_address: String,
+    },
+    /// Allow the operator to claim on behalf of the vesting account.
+    /// The claimed tokens are sent to the recipient or the vesting account.
+    AuthorizeOperator {
+        operator: String,
         recipient: Option<String>,
+        expires: Option<Expiration>,
+    },
+    RevokeOperator {
+        operator: String,
+    },
+    /// Delegate the unclaimed tokens of the vesting account to the validator,
+    /// only the bonded native token can be delegated.
+    /// The staking rewards are sent to the vesting account.
+    Delegate {
+        denom: Denom,
+        grant_id: Option<u64>,
+        validator: String,
+        amount: Uint128,
+    },
+    /// Undelegate from the validator, also available to the master.
+    /// The undelegated tokens become claimable after the unbonding period.
+    Undelegate {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+        validator: String,
+        amount: Uint128,
+    },
+    /// Withdraw the staking rewards of the delegation to the validator
+    WithdrawRewards {
+        denom: Denom,
+        grant_id: Option<u64>,
+        validator: String,
+    },
+
+    ////////////////////////
+    /// Operator Operations ///
+    ////////////////////////
+    /// Claim on behalf of the vesting account
+    ClaimFor {
+        address: String,
+        denoms: Vec<Denom>,
+    },
+
+    ////////////////////////
+    /// Admin Operations ///
+    ////////////////////////
+    /// Block every state-changing operation except the admin operations
+    Pause {},
+    Unpause {},
+    /// Replace the accepted vesting denoms, any denom is accepted when not given
+    UpdateAllowedDenoms {
+        allowed_denoms: Option<Vec<Denom>>,
+    },
+    UpdateAdmin {
+        admin: String,
+    },
+    /// Follow the unbonding period of the chain in second unit
+    UpdateUnbondingPeriod {
+        unbonding_period: u64,
     },
 }
 
@@ -45,16 +193,112 @@ pub enum Cw20HookMsg {
         address: String,
         vesting_schedule: VestingSchedule,
     },
+    /// Register multiple vesting accounts with token transfer
+    RegisterVestingAccounts {
+        vesting_accounts: Vec<VestingAccountMsg>,
+    },
+    /// Amend vesting account with token transfer,
+    /// only available from the master_address
+    AmendVestingAccount {
+        address: String,
+        grant_id: Option<u64>,
+        end_time: Option<Uint64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingAccountMsg {
+    pub master_address: Option<String>, // if given, the vesting account can be unregistered
+    pub address: String,
+    pub vesting_schedule: VestingSchedule,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
+    Config {},
+    /// List the grants of the given address grouped by denom, ordered by grant_id in a denom.
+    /// All grants of start_after are skipped unless start_after_grant is given,
+    /// then the listing continues after that grant of the start_after denom
     VestingAccount {
         address: String,
         start_after: Option<Denom>,
+        start_after_grant: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Sum of all grants of the given address in the denom
+    VestingSummary {
+        address: String,
+        denom: Denom,
+    },
+    /// List all grants ordered by grant_id
+    VestingAccounts {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// List all grants of the given denom ordered by grant_id
+    VestingAccountsByDenom {
+        denom: Denom,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// List all grants controlled by the given master_address ordered by grant_id
+    VestingAccountsByMaster {
+        master_address: String,
+        start_after: Option<u64>,
         limit: Option<u32>,
     },
+    /// Running totals of the given denom
+    DenomSummary {
+        denom: Denom,
+    },
+    /// List the claim operators of the given address
+    Operators {
+        address: String,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Vested and claimable amounts of the given grant at at_time,
+    /// summed over all grants of the denom when grant_id is not given.
+    /// Block height based schedules are evaluated at at_height,
+    /// which defaults to the current block height.
+    /// The claimable amount excludes the tokens still bonded or unbonding at at_time,
+    /// assuming no further staking operations
+    SimulateVesting {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+        at_time: u64,
+        at_height: Option<u64>,
+    },
+    /// Vested and claimable amounts of the given schedule at each of at_times,
+    /// without registering it. at_times are block heights
+    /// for the block height based schedules
+    PreviewSchedule {
+        schedule: VestingSchedule,
+        at_times: Vec<u64>,
+    },
+    /// Bonded and unbonding amounts of the given grant
+    Stake {
+        address: String,
+        denom: Denom,
+        grant_id: Option<u64>,
+    },
+    /// Unvested plus unclaimed amount of all grants of the address in the denom
+    /// at the beginning of the given block height, defaults to the current state
+    VotingPower {
+        address: String,
+        denom: Denom,
+        height: Option<u64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct ConfigResponse {
+    pub admin: Option<String>,
+    pub unbonding_period: u64,
+    pub paused: bool,
+    pub allowed_denoms: Option<Vec<Denom>>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
@@ -63,8 +307,93 @@ pub struct VestingAccountResponse {
     pub vestings: Vec<VestingData>,
 }
 
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct VestingSummaryResponse {
+    pub address: String,
+    pub vesting_denom: Denom,
+    pub grant_ids: Vec<u64>,
+    pub vesting_amount: Uint128,
+    pub vested_amount: Uint128,
+    pub claimable_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct VestingAccountsResponse {
+    pub vesting_accounts: Vec<VestingAccountData>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct VestingAccountData {
+    pub address: String,
+    pub vesting: VestingData,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct DenomSummaryResponse {
+    pub denom: Denom,
+    pub deposited_amount: Uint128, // total amount deposited for vesting
+    pub claimed_amount: Uint128,   // total amount released to vesting accounts
+    pub returned_amount: Uint128,  // total unvested amount returned on deregistration
+    pub slashed_amount: Uint128,   // total amount lost to the slashing of delegations
+    pub unclaimed_amount: Uint128, // total amount not yet claimed by the remaining grants
+    pub locked_amount: Uint128,    // total amount not yet vested at the current block time
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct OperatorsResponse {
+    pub operators: Vec<OperatorInfo>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct OperatorInfo {
+    pub operator: String,
+    pub recipient: Option<String>,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct StakeResponse {
+    pub delegated_amount: Uint128,
+    pub delegations: Vec<DelegationInfo>,
+    pub unbondings: Vec<UnbondingInfo>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct DelegationInfo {
+    pub validator: String,
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct UnbondingInfo {
+    pub amount: Uint128,
+    pub release_time: Uint64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct VotingPowerResponse {
+    pub address: String,
+    pub denom: Denom,
+    pub height: u64,
+    pub voting_power: Uint128,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct VestingSimulationResponse {
+    pub vesting_amount: Uint128,
+    pub simulations: Vec<VestingSimulation>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct VestingSimulation {
+    pub at_time: u64,
+    pub vested_amount: Uint128,
+    pub claimable_amount: Uint128, // vested but not yet claimed amount
+}
+
 #[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
 pub struct VestingData {
+    pub grant_id: u64,
     pub master_address: Option<String>,
     pub vesting_denom: Denom,
     pub vesting_amount: Uint128,
@@ -79,8 +408,17 @@ pub enum VestingSchedule {
     /// LinearVesting is used to vest tokens linearly during a time period.
     /// The total_amount will be vested during this period.
     LinearVesting {
-        start_time: String,      // vesting start time in second unit
-        end_time: String,        // vesting end time in second unit
+        start_time: Uint64,      // vesting start time in second unit
+        end_time: Uint64,        // vesting end time in second unit
+        vesting_amount: Uint128, // total vesting amount
+    },
+    /// LinearVestingWithCliff is used to vest tokens linearly during a time period,
+    /// but nothing can be claimed before the cliff_time.
+    /// At the cliff_time, the whole amount accrued since start_time is unlocked at once.
+    LinearVestingWithCliff {
+        start_time: Uint64,      // vesting start time in second unit
+        end_time: Uint64,        // vesting end time in second unit
+        cliff_time: Uint64,      // vesting cliff time in second unit
         vesting_amount: Uint128, // total vesting amount
     },
     /// PeriodicVesting is used to vest tokens
@@ -89,23 +427,292 @@ pub enum VestingSchedule {
     /// (end_time - start_time) should be multiple of vesting_interval
     /// deposit_amount = amount * ((end_time - start_time) / vesting_interval + 1)
     PeriodicVesting {
-        start_time: String,       // vesting start time in second unit
-        end_time: String,         // vesting end time in second unit
-        vesting_interval: String, // vesting interval in second unit
+        start_time: Uint64,       // vesting start time in second unit
+        end_time: Uint64,         // vesting end time in second unit
+        vesting_interval: Uint64, // vesting interval in second unit
+        amount: Uint128,          // the amount will be vested in a interval
+    },
+    /// CustomVesting is used to vest tokens by an arbitrary unlock calendar.
+    /// Each tranche is unlocked at once when its time is reached.
+    /// Tranche times must be strictly increasing and
+    /// deposit_amount = sum of tranche amounts
+    CustomVesting {
+        tranches: Vec<(Uint64, Uint128)>, // (unlock time in second unit, unlock amount)
+    },
+    /// LinearVestingByHeight is LinearVesting evaluated against the block height.
+    LinearVestingByHeight {
+        start_height: Uint64,    // vesting start block height
+        end_height: Uint64,      // vesting end block height
+        vesting_amount: Uint128, // total vesting amount
+    },
+    /// PeriodicVestingByHeight is PeriodicVesting evaluated against the block height.
+    /// deposit_amount = amount * ((end_height - start_height) / vesting_interval + 1)
+    PeriodicVestingByHeight {
+        start_height: Uint64,     // vesting start block height
+        end_height: Uint64,       // vesting end block height
+        vesting_interval: Uint64, // vesting interval in block unit
         amount: Uint128,          // the amount will be vested in a interval
     },
 }
 
 impl VestingSchedule {
+    /// returns the time based counterpart of a block height based schedule,
+    /// which is evaluated with the block height in place of the block time
+    fn as_time_schedule(&self) -> VestingSchedule {
+        match self {
+            VestingSchedule::LinearVestingByHeight {
+                start_height,
+                end_height,
+                vesting_amount,
+            } => VestingSchedule::LinearVesting {
+                start_time: *start_height,
+                end_time: *end_height,
+                vesting_amount: *vesting_amount,
+            },
+            VestingSchedule::PeriodicVestingByHeight {
+                start_height,
+                end_height,
+                vesting_interval,
+                amount,
+            } => VestingSchedule::PeriodicVesting {
+                start_time: *start_height,
+                end_time: *end_height,
+                vesting_interval: *vesting_interval,
+                amount: *amount,
+            },
+            _ => self.clone(),
+        }
+    }
+
+    fn as_height_schedule(&self) -> VestingSchedule {
+        match self {
+            VestingSchedule::LinearVesting {
+                start_time,
+                end_time,
+                vesting_amount,
+            } => VestingSchedule::LinearVestingByHeight {
+                start_height: *start_time,
+                end_height: *end_time,
+                vesting_amount: *vesting_amount,
+            },
+            VestingSchedule::PeriodicVesting {
+                start_time,
+                end_time,
+                vesting_interval,
+                amount,
+            } => VestingSchedule::PeriodicVestingByHeight {
+                start_height: *start_time,
+                end_height: *end_time,
+                vesting_interval: *vesting_interval,
+                amount: *amount,
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// validate the schedule to be registered at the given block with deposit_amount
+    pub fn validate(
+        &self,
+        block: &BlockInfo,
+        deposit_amount: Uint128,
+    ) -> Result<(), ContractError> {
+        match self {
+            VestingSchedule::LinearVesting {
+                start_time,
+                end_time,
+                ..
+            } => validate_period(block.time.seconds(), *start_time, *end_time, "time")?,
+            VestingSchedule::LinearVestingWithCliff {
+                start_time,
+                end_time,
+                cliff_time,
+                ..
+            } => {
+                validate_period(block.time.seconds(), *start_time, *end_time, "time")?;
+                if cliff_time < start_time || cliff_time > end_time {
+                    return Err(ContractError::invalid_schedule(
+                        "assert(start_time <= cliff_time <= end_time)",
+                    ));
+                }
+            }
+            VestingSchedule::PeriodicVesting {
+                start_time,
+                end_time,
+                vesting_interval,
+                ..
+            } => {
+                validate_period(block.time.seconds(), *start_time, *end_time, "time")?;
+                validate_interval(*start_time, *end_time, *vesting_interval, "time")?;
+            }
+            VestingSchedule::CustomVesting { tranches } => {
+                if tranches.is_empty() {
+                    return Err(ContractError::invalid_schedule(
+                        "assert(tranches.len() > 0)",
+                    ));
+                }
+
+                if tranches[0].0.u64() < block.time.seconds() {
+                    return Err(ContractError::invalid_schedule(
+                        "assert(start_time >= block_time)",
+                    ));
+                }
+
+                for (i, (time, amount)) in tranches.iter().enumerate() {
+                    if amount.is_zero() {
+                        return Err(ContractError::invalid_schedule(
+                            "assert(tranche_amount > 0)",
+                        ));
+                    }
+
+                    if i != 0 && *time <= tranches[i - 1].0 {
+                        return Err(ContractError::invalid_schedule(
+                            "assert(tranche times are strictly increasing)",
+                        ));
+                    }
+                }
+            }
+            VestingSchedule::LinearVestingByHeight {
+                start_height,
+                end_height,
+                ..
+            } => validate_period(block.height, *start_height, *end_height, "height")?,
+            VestingSchedule::PeriodicVestingByHeight {
+                start_height,
+                end_height,
+                vesting_interval,
+                ..
+            } => {
+                validate_period(block.height, *start_height, *end_height, "height")?;
+                validate_interval(*start_height, *end_height, *vesting_interval, "height")?;
+            }
+        }
+
+        let vesting_amount = self.total_amount()?;
+        if vesting_amount.is_zero() {
+            return Err(ContractError::invalid_schedule(
+                "assert(vesting_amount > 0)",
+            ));
+        }
+
+        if vesting_amount != deposit_amount {
+            return Err(ContractError::DepositMismatch {
+                expected: vesting_amount,
+                got: deposit_amount,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// total amount of tokens which will be vested by this schedule
+    pub fn total_amount(&self) -> StdResult<Uint128> {
+        match self {
+            VestingSchedule::LinearVestingByHeight { .. }
+            | VestingSchedule::PeriodicVestingByHeight { .. } => {
+                self.as_time_schedule().total_amount()
+            }
+            VestingSchedule::LinearVesting { vesting_amount, .. }
+            | VestingSchedule::LinearVestingWithCliff { vesting_amount, .. } => Ok(*vesting_amount),
+            VestingSchedule::PeriodicVesting {
+                start_time,
+                end_time,
+                vesting_interval,
+                amount,
+            } => {
+                let num_interval = num_interval(*start_time, *end_time, *vesting_interval)?;
+                Ok(amount.checked_mul(Uint128::from(num_interval))?)
+            }
+            VestingSchedule::CustomVesting { tranches } => {
+                let mut total_amount = Uint128::zero();
+                for (_, amount) in tranches.iter() {
+                    total_amount = total_amount.checked_add(*amount)?;
+                }
+
+                Ok(total_amount)
+            }
+        }
+    }
+
+    /// returns the schedule extended to end_time and topped up with top_up_amount,
+    /// end_time is the end block height for block height based schedules
+    pub fn amend(
+        &self,
+        end_time: Option<Uint64>,
+        top_up_amount: Uint128,
+    ) -> Result<Self, ContractError> {
+        match self {
+            VestingSchedule::LinearVestingByHeight { .. }
+            | VestingSchedule::PeriodicVestingByHeight { .. } => Ok(self
+                .as_time_schedule()
+                .amend(end_time, top_up_amount)?
+                .as_height_schedule()),
+            VestingSchedule::LinearVesting {
+                start_time,
+                end_time: old_end_time,
+                vesting_amount,
+            } => Ok(VestingSchedule::LinearVesting {
+                start_time: *start_time,
+                end_time: extend_end_time(*old_end_time, end_time)?,
+                vesting_amount: vesting_amount.checked_add(top_up_amount)?,
+            }),
+            VestingSchedule::LinearVestingWithCliff {
+                start_time,
+                end_time: old_end_time,
+                cliff_time,
+                vesting_amount,
+            } => Ok(VestingSchedule::LinearVestingWithCliff {
+                start_time: *start_time,
+                end_time: extend_end_time(*old_end_time, end_time)?,
+                cliff_time: *cliff_time,
+                vesting_amount: vesting_amount.checked_add(top_up_amount)?,
+            }),
+            VestingSchedule::PeriodicVesting {
+                start_time,
+                end_time: old_end_time,
+                vesting_interval,
+                amount,
+            } => {
+                let end_time = extend_end_time(*old_end_time, end_time)?;
+                validate_interval(*start_time, end_time, *vesting_interval, "time")?;
+
+                Ok(VestingSchedule::PeriodicVesting {
+                    start_time: *start_time,
+                    end_time,
+                    vesting_interval: *vesting_interval,
+                    amount: *amount,
+                })
+            }
+            VestingSchedule::CustomVesting { .. } => Err(ContractError::invalid_schedule(
+                "custom vesting cannot be amended",
+            )),
+        }
+    }
+
+    /// vested amount at the given block,
+    /// block height based schedules are evaluated against the block height
+    pub fn vested_amount_at(&self, block: &BlockInfo) -> StdResult<Uint128> {
+        match self {
+            VestingSchedule::LinearVestingByHeight { .. }
+            | VestingSchedule::PeriodicVestingByHeight { .. } => self.vested_amount(block.height),
+            _ => self.vested_amount(block.time.seconds()),
+        }
+    }
+
+    /// vested amount at block_time,
+    /// block_time is the block height for block height based schedules
     pub fn vested_amount(&self, block_time: u64) -> StdResult<Uint128> {
         match self {
+            VestingSchedule::LinearVestingByHeight { .. }
+            | VestingSchedule::PeriodicVestingByHeight { .. } => {
+                self.as_time_schedule().vested_amount(block_time)
+            }
             VestingSchedule::LinearVesting {
                 start_time,
                 end_time,
                 vesting_amount,
             } => {
-                let start_time = start_time.parse::<u64>().unwrap();
-                let end_time = end_time.parse::<u64>().unwrap();
+                let start_time = start_time.u64();
+                let end_time = end_time.u64();
 
                 if block_time <= start_time {
                     return Ok(Uint128::zero());
@@ -121,45 +728,166 @@ impl VestingSchedule {
 
                 Ok(vested_token)
             }
+            VestingSchedule::LinearVestingWithCliff {
+                start_time,
+                end_time,
+                cliff_time,
+                vesting_amount,
+            } => {
+                if block_time < cliff_time.u64() {
+                    return Ok(Uint128::zero());
+                }
+
+                VestingSchedule::LinearVesting {
+                    start_time: *start_time,
+                    end_time: *end_time,
+                    vesting_amount: *vesting_amount,
+                }
+                .vested_amount(block_time)
+            }
             VestingSchedule::PeriodicVesting {
                 start_time,
                 end_time,
                 vesting_interval,
                 amount,
             } => {
-                let start_time = start_time.parse::<u64>().unwrap();
-                let end_time = end_time.parse::<u64>().unwrap();
-                let vesting_interval = vesting_interval.parse::<u64>().unwrap();
-
-                if block_time < start_time {
+                if block_time < start_time.u64() {
                     return Ok(Uint128::zero());
                 }
 
-                let num_interval = 1 + (end_time - start_time) / vesting_interval;
-                if block_time >= end_time {
-                    return Ok(amount.checked_mul(Uint128::from(num_interval))?);
+                if block_time >= end_time.u64() {
+                    return self.total_amount();
                 }
 
-                let passed_interval = 1 + (block_time - start_time) / vesting_interval;
+                let passed_interval =
+                    num_interval(*start_time, Uint64::new(block_time), *vesting_interval)?;
                 Ok(amount.checked_mul(Uint128::from(passed_interval))?)
             }
+            VestingSchedule::CustomVesting { tranches } => {
+                let mut vested_token = Uint128::zero();
+                for (time, amount) in tranches.iter() {
+                    if block_time < time.u64() {
+                        break;
+                    }
+
+                    vested_token = vested_token.checked_add(*amount)?;
+                }
+
+                Ok(vested_token)
+            }
         }
     }
 }
 
+fn validate_period(now: u64, start: Uint64, end: Uint64, unit: &str) -> Result<(), ContractError> {
+    if start.u64() < now {
+        return Err(ContractError::invalid_schedule(&format!(
+            "assert(start_{} >= block_{})",
+            unit, unit
+        )));
+    }
+
+    if end <= start {
+        return Err(ContractError::invalid_schedule(&format!(
+            "assert(end_{} > start_{})",
+            unit, unit
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_interval(
+    start: Uint64,
+    end: Uint64,
+    interval: Uint64,
+    unit: &str,
+) -> Result<(), ContractError> {
+    if interval.is_zero() {
+        return Err(ContractError::invalid_schedule(
+            "assert(vesting_interval > 0)",
+        ));
+    }
+
+    let remainder = end
+        .checked_sub(start)?
+        .checked_rem(interval)
+        .map_err(|_| ContractError::invalid_schedule("assert(vesting_interval > 0)"))?;
+    if !remainder.is_zero() {
+        return Err(ContractError::invalid_schedule(&format!(
+            "assert((end_{} - start_{}) % vesting_interval == 0)",
+            unit, unit
+        )));
+    }
+
+    Ok(())
+}
+
+/// number of intervals started in [start, end]
+fn num_interval(start: Uint64, end: Uint64, interval: Uint64) -> StdResult<u64> {
+    let period = end.checked_sub(start)?;
+    let passed_interval = period
+        .checked_div(interval)
+        .map_err(|_| StdError::generic_err("assert(vesting_interval > 0)"))?;
+    Ok(1 + passed_interval.u64())
+}
+
+fn extend_end_time(
+    old_end_time: Uint64,
+    end_time: Option<Uint64>,
+) -> Result<Uint64, ContractError> {
+    let end_time = end_time.unwrap_or(old_end_time);
+    if end_time < old_end_time {
+        return Err(ContractError::invalid_schedule(
+            "assert(new_end_time >= end_time)",
+        ));
+    }
+
+    Ok(end_time)
+}
+
 #[test]
 fn linear_vesting_vested_amount() {
     let schedule = VestingSchedule::LinearVesting {
-        start_time: "100".to_string(),
-        end_time: "110".to_string(),
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+
+    assert_eq!(schedule.vested_amount(100).unwrap(), Uint128::zero());
+    assert_eq!(
+        schedule.vested_amount(105).unwrap(),
+        Uint128::new(500000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(110).unwrap(),
+        Uint128::new(1000000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(115).unwrap(),
+        Uint128::new(1000000u128)
+    );
+}
+
+#[test]
+fn linear_vesting_with_cliff_vested_amount() {
+    let schedule = VestingSchedule::LinearVestingWithCliff {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        cliff_time: Uint64::new(105),
         vesting_amount: Uint128::new(1000000u128),
     };
 
     assert_eq!(schedule.vested_amount(100).unwrap(), Uint128::zero());
+    assert_eq!(schedule.vested_amount(104).unwrap(), Uint128::zero());
     assert_eq!(
         schedule.vested_amount(105).unwrap(),
         Uint128::new(500000u128)
     );
+    assert_eq!(
+        schedule.vested_amount(108).unwrap(),
+        Uint128::new(800000u128)
+    );
     assert_eq!(
         schedule.vested_amount(110).unwrap(),
         Uint128::new(1000000u128)
@@ -173,9 +901,9 @@ fn linear_vesting_vested_amount() {
 #[test]
 fn periodic_vesting_vested_amount() {
     let schedule = VestingSchedule::PeriodicVesting {
-        start_time: "105".to_string(),
-        end_time: "110".to_string(),
-        vesting_interval: "5".to_string(),
+        start_time: Uint64::new(105),
+        end_time: Uint64::new(110),
+        vesting_interval: Uint64::new(5),
         amount: Uint128::new(500000u128),
     };
 
@@ -193,3 +921,134 @@ fn periodic_vesting_vested_amount() {
         Uint128::new(1000000u128)
     );
 }
+
+#[test]
+fn linear_vesting_by_height_vested_amount() {
+    let schedule = VestingSchedule::LinearVestingByHeight {
+        start_height: Uint64::new(1000),
+        end_height: Uint64::new(1100),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+
+    let mut block = cosmwasm_std::testing::mock_env().block;
+    block.time = cosmwasm_std::Timestamp::from_seconds(1100);
+    block.height = 1000;
+    assert_eq!(schedule.vested_amount_at(&block).unwrap(), Uint128::zero());
+
+    block.height = 1050;
+    assert_eq!(
+        schedule.vested_amount_at(&block).unwrap(),
+        Uint128::new(500000u128)
+    );
+
+    block.height = 1200;
+    assert_eq!(
+        schedule.vested_amount_at(&block).unwrap(),
+        Uint128::new(1000000u128)
+    );
+}
+
+#[test]
+fn custom_vesting_vested_amount() {
+    let schedule = VestingSchedule::CustomVesting {
+        tranches: vec![
+            (Uint64::new(100), Uint128::new(100000u128)),
+            (Uint64::new(110), Uint128::new(300000u128)),
+            (Uint64::new(130), Uint128::new(600000u128)),
+        ],
+    };
+
+    assert_eq!(schedule.vested_amount(99).unwrap(), Uint128::zero());
+    assert_eq!(
+        schedule.vested_amount(100).unwrap(),
+        Uint128::new(100000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(120).unwrap(),
+        Uint128::new(400000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(130).unwrap(),
+        Uint128::new(1000000u128)
+    );
+    assert_eq!(
+        schedule.vested_amount(200).unwrap(),
+        Uint128::new(1000000u128)
+    );
+}
+
+#[test]
+fn vesting_schedule_legacy_string_form() {
+    let schedule: VestingSchedule = cosmwasm_std::from_slice(
+        br#"{"periodic_vesting":{"start_time":"105","end_time":"110","vesting_interval":"5","amount":"500000"}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        schedule,
+        VestingSchedule::PeriodicVesting {
+            start_time: Uint64::new(105),
+            end_time: Uint64::new(110),
+            vesting_interval: Uint64::new(5),
+            amount: Uint128::new(500000u128),
+        }
+    );
+}
+
+#[test]
+fn vesting_schedule_validate() {
+    let mut block = cosmwasm_std::testing::mock_env().block;
+    block.time = cosmwasm_std::Timestamp::from_seconds(100);
+
+    let schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(99),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+    assert_eq!(
+        schedule.validate(&block, Uint128::new(1000000u128)),
+        Err(ContractError::invalid_schedule(
+            "assert(start_time >= block_time)"
+        ))
+    );
+
+    let schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(110),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+    assert_eq!(
+        schedule.validate(&block, Uint128::new(1000000u128)),
+        Err(ContractError::invalid_schedule(
+            "assert(end_time > start_time)"
+        ))
+    );
+
+    let schedule = VestingSchedule::PeriodicVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_interval: Uint64::new(3),
+        amount: Uint128::new(500000u128),
+    };
+    assert_eq!(
+        schedule.validate(&block, Uint128::new(2000000u128)),
+        Err(ContractError::invalid_schedule(
+            "assert((end_time - start_time) % vesting_interval == 0)"
+        ))
+    );
+
+    let schedule = VestingSchedule::PeriodicVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_interval: Uint64::new(5),
+        amount: Uint128::new(500000u128),
+    };
+    assert_eq!(
+        schedule.validate(&block, Uint128::new(1000000u128)),
+        Err(ContractError::DepositMismatch {
+            expected: Uint128::new(1500000u128),
+            got: Uint128::new(1000000u128),
+        })
+    );
+    assert_eq!(schedule.validate(&block, Uint128::new(1500000u128)), Ok(()));
+}
@@ -0,0 +1,631 @@
+use std::convert::TryInto;
+
+use cosmwasm_std::{
+    BankMsg, Coin, CosmosMsg, Decimal, Deps, DepsMut, DistributionMsg, Env, Fraction, MessageInfo,
+    Order, Response, StakingMsg, StdError, StdResult, Storage, SubMsg, Uint128, Uint64,
+};
+
+use cw20::Denom;
+use cw_storage_plus::U64Key;
+
+use crate::error::ContractError;
+use crate::msg::{DelegationInfo, StakeResponse, UnbondingInfo};
+use crate::state::{
+    denom_to_key, load_grant, save_vesting_account, vesting_accounts, Delegation, PendingStaking,
+    StakingAction, Unbonding, Validator, VestingAccount, CONFIG, DELEGATIONS, DENOM_SUMMARIES,
+    PENDING_STAKING, STAKES, UNBONDING_QUEUE, VALIDATORS,
+};
+
+pub const WITHDRAW_REWARDS_REPLY_ID: u64 = 1;
+
+pub fn delegate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: Denom,
+    grant_id: Option<u64>,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let denom = bonded_denom(deps.as_ref(), &denom)?;
+    let address = info.sender.to_string();
+    let account = load_grant(
+        deps.storage,
+        &address,
+        &Denom::Native(denom.clone()),
+        grant_id,
+    )?;
+
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let available = unstaked_amount(deps.storage, env.block.time.seconds(), &account)?;
+    if amount > available {
+        return Err(ContractError::InsufficientFunds { available });
+    }
+
+    withdraw_rewards_and_apply(
+        deps,
+        env,
+        PendingStaking {
+            grant_id: account.grant_id,
+            address,
+            denom,
+            validator,
+            action: StakingAction::Delegate { amount },
+            balances: vec![],
+        },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn undelegate(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+    validator: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let denom = bonded_denom(deps.as_ref(), &denom)?;
+    let account = load_grant(
+        deps.storage,
+        &address,
+        &Denom::Native(denom.clone()),
+        grant_id,
+    )?;
+    if info.sender != address && account.master_address != Some(info.sender.to_string()) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut delegation = DELEGATIONS
+        .may_load(
+            deps.storage,
+            (U64Key::new(account.grant_id), validator.as_str()),
+        )?
+        .ok_or(ContractError::NoDelegation {})?;
+
+    // the recorded amount is larger than the real delegation after a slash
+    let validator_info = load_validator(deps.as_ref(), &env, &validator)?;
+    settle_slashes(&mut delegation, &validator_info)?;
+
+    if amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    if amount > delegation.amount {
+        return Err(ContractError::InsufficientFunds {
+            available: delegation.amount,
+        });
+    }
+
+    withdraw_rewards_and_apply(
+        deps,
+        env,
+        PendingStaking {
+            grant_id: account.grant_id,
+            address,
+            denom,
+            validator,
+            action: StakingAction::Undelegate { amount },
+            balances: vec![],
+        },
+    )
+}
+
+pub fn withdraw_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: Denom,
+    grant_id: Option<u64>,
+    validator: String,
+) -> Result<Response, ContractError> {
+    let denom = bonded_denom(deps.as_ref(), &denom)?;
+    let address = info.sender.to_string();
+    let account = load_grant(
+        deps.storage,
+        &address,
+        &Denom::Native(denom.clone()),
+        grant_id,
+    )?;
+
+    if DELEGATIONS
+        .may_load(
+            deps.storage,
+            (U64Key::new(account.grant_id), validator.as_str()),
+        )?
+        .is_none()
+    {
+        return Err(ContractError::NoDelegation {});
+    }
+
+    withdraw_rewards_and_apply(
+        deps,
+        env,
+        PendingStaking {
+            grant_id: account.grant_id,
+            address,
+            denom,
+            validator,
+            action: StakingAction::WithdrawRewards {},
+            balances: vec![],
+        },
+    )
+}
+
+/// Rewards of all delegations to a validator are paid to the contract at once,
+/// so they are withdrawn and shared by the current delegations
+/// before the staking action changes them.
+fn withdraw_rewards_and_apply(
+    deps: DepsMut,
+    env: Env,
+    mut pending: PendingStaking,
+) -> Result<Response, ContractError> {
+    let validator = VALIDATORS
+        .may_load(deps.storage, &pending.validator)?
+        .unwrap_or_default();
+
+    // nothing to withdraw without the contract delegation
+    if validator.delegated_amount.is_zero() {
+        return apply_staking(deps, env, pending);
+    }
+
+    pending.balances = deps.querier.query_all_balances(&env.contract.address)?;
+    PENDING_STAKING.save(deps.storage, &pending)?;
+
+    Ok(Response::new().add_submessage(SubMsg::reply_on_success(
+        DistributionMsg::WithdrawDelegatorReward {
+            validator: pending.validator,
+        },
+        WITHDRAW_REWARDS_REPLY_ID,
+    )))
+}
+
+/// the balance increase since the withdrawal is the validator rewards
+pub fn withdraw_rewards_reply(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending = PENDING_STAKING.load(deps.storage)?;
+    PENDING_STAKING.remove(deps.storage);
+
+    let mut validator = load_validator(deps.as_ref(), &env, &pending.validator)?;
+    for balance in deps.querier.query_all_balances(&env.contract.address)? {
+        let balance_before = pending
+            .balances
+            .iter()
+            .find(|coin| coin.denom == balance.denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+
+        let rewards = balance.amount.saturating_sub(balance_before);
+        if rewards.is_zero() || validator.delegated_amount.is_zero() {
+            continue;
+        }
+
+        let index = reward_index(&validator.reward_indexes, &balance.denom)
+            + Decimal::from_ratio(rewards, validator.delegated_amount);
+        set_reward_index(&mut validator.reward_indexes, &balance.denom, index);
+    }
+
+    VALIDATORS.save(deps.storage, &pending.validator, &validator)?;
+    apply_staking(deps, env, pending)
+}
+
+fn apply_staking(
+    deps: DepsMut,
+    env: Env,
+    pending: PendingStaking,
+) -> Result<Response, ContractError> {
+    let address = pending.address.as_str();
+    let grant_key = U64Key::new(pending.grant_id);
+    let delegation_key = (grant_key.clone(), pending.validator.as_str());
+
+    let mut validator = load_validator(deps.as_ref(), &env, &pending.validator)?;
+    let mut delegation = DELEGATIONS
+        .may_load(deps.storage, delegation_key.clone())?
+        .unwrap_or_default();
+    let mut stake = STAKES
+        .may_load(deps.storage, grant_key.clone())?
+        .unwrap_or_default();
+    stake.release(env.block.time.seconds());
+
+    // the slashed tokens are lost from the grant, and from the pending return
+    // of a terminated grant once the unclaimed amount is used up
+    let denom_key = denom_to_key(Denom::Native(pending.denom.to_string()));
+    let slashed_amount = settle_slashes(&mut delegation, &validator)?;
+    if !slashed_amount.is_zero() {
+        stake.delegated_amount = stake.delegated_amount.checked_sub(slashed_amount)?;
+
+        let mut account = vesting_accounts().load(deps.storage, grant_key.clone())?;
+        let unclaimed_slash = account
+            .vesting_amount
+            .checked_sub(account.claimed_amount)?
+            .min(slashed_amount);
+        account.vesting_amount = account.vesting_amount.checked_sub(unclaimed_slash)?;
+        account.pending_return_amount = account
+            .pending_return_amount
+            .checked_sub(slashed_amount.checked_sub(unclaimed_slash)?)?;
+        save_vesting_account(deps.storage, &account, env.block.height)?;
+
+        DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+            let mut summary = summary.unwrap_or_default();
+            summary.slashed_amount = summary.slashed_amount.checked_add(slashed_amount)?;
+            summary.staked_amount = summary.staked_amount.checked_sub(slashed_amount)?;
+            Ok(summary)
+        })?;
+    }
+
+    // settle the rewards accrued since the last settlement
+    let mut rewards: Vec<Coin> = vec![];
+    for (denom, index) in validator.reward_indexes.iter() {
+        let amount = delegation.amount * (*index - reward_index(&delegation.reward_indexes, denom));
+        if !amount.is_zero() {
+            rewards.push(Coin {
+                denom: denom.to_string(),
+                amount,
+            });
+        }
+    }
+    delegation.reward_indexes = validator.reward_indexes.clone();
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let (action, amount) = match pending.action {
+        StakingAction::Delegate { amount } => {
+            delegation.amount = delegation.amount.checked_add(amount)?;
+            validator.delegated_amount = validator.delegated_amount.checked_add(amount)?;
+            stake.delegated_amount = stake.delegated_amount.checked_add(amount)?;
+            DENOM_SUMMARIES.update(deps.storage, &denom_key, |summary| -> StdResult<_> {
+                let mut summary = summary.unwrap_or_default();
+                summary.staked_amount = summary.staked_amount.checked_add(amount)?;
+                Ok(summary)
+            })?;
+            messages.push(
+                StakingMsg::Delegate {
+                    validator: pending.validator.to_string(),
+                    amount: Coin {
+                        denom: pending.denom.to_string(),
+                        amount,
+                    },
+                }
+                .into(),
+            );
+
+            ("delegate", amount)
+        }
+        StakingAction::Undelegate { amount } => {
+            let unbonding_period = CONFIG.load(deps.storage)?.unbonding_period;
+            let release_time =
+                Uint64::new(env.block.time.seconds()).checked_add(Uint64::new(unbonding_period))?;
+            delegation.amount = delegation.amount.checked_sub(amount)?;
+            validator.delegated_amount = validator.delegated_amount.checked_sub(amount)?;
+            stake.delegated_amount = stake.delegated_amount.checked_sub(amount)?;
+            stake.unbondings.push(Unbonding {
+                amount,
+                release_time,
+            });
+            UNBONDING_QUEUE.update(
+                deps.storage,
+                (
+                    &denom_key,
+                    U64Key::new(release_time.u64()),
+                    U64Key::new(pending.grant_id),
+                ),
+                |unbonding| -> StdResult<_> {
+                    Ok(unbonding.unwrap_or_default().checked_add(amount)?)
+                },
+            )?;
+            messages.push(
+                StakingMsg::Undelegate {
+                    validator: pending.validator.to_string(),
+                    amount: Coin {
+                        denom: pending.denom.to_string(),
+                        amount,
+                    },
+                }
+                .into(),
+            );
+
+            ("undelegate", amount)
+        }
+        StakingAction::WithdrawRewards {} => ("withdraw_rewards", Uint128::zero()),
+    };
+
+    if delegation.amount.is_zero() {
+        DELEGATIONS.remove(deps.storage, delegation_key.clone());
+    } else {
+        DELEGATIONS.save(deps.storage, delegation_key, &delegation)?;
+    }
+
+    // the slash index is kept for the delegations wiped out by the slashes
+    if validator.delegated_amount.is_zero() && validator.slash_index == Decimal::one() {
+        VALIDATORS.remove(deps.storage, &pending.validator);
+    } else {
+        VALIDATORS.save(deps.storage, &pending.validator, &validator)?;
+    }
+
+    if stake.delegated_amount.is_zero() && stake.unbondings.is_empty() {
+        STAKES.remove(deps.storage, grant_key);
+    } else {
+        STAKES.save(deps.storage, grant_key, &stake)?;
+    }
+
+    let rewards_attr = rewards
+        .iter()
+        .map(|coin| coin.to_string())
+        .collect::<Vec<String>>()
+        .join(",");
+    if !rewards.is_empty() {
+        messages.push(
+            BankMsg::Send {
+                to_address: address.to_string(),
+                amount: rewards,
+            }
+            .into(),
+        );
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", action),
+        ("address", address),
+        ("grant_id", &pending.grant_id.to_string()),
+        ("validator", pending.validator.as_str()),
+        ("amount", &amount.to_string()),
+        ("rewards", &rewards_attr),
+        ("slashed_amount", &slashed_amount.to_string()),
+    ]))
+}
+
+pub fn query_stake(
+    deps: Deps,
+    env: Env,
+    address: String,
+    denom: Denom,
+    grant_id: Option<u64>,
+) -> StdResult<StakeResponse> {
+    let account = load_grant(deps.storage, &address, &denom, grant_id)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let grant_key = U64Key::new(account.grant_id);
+    let mut stake = STAKES
+        .may_load(deps.storage, grant_key.clone())?
+        .unwrap_or_default();
+    stake.release(env.block.time.seconds());
+
+    // the delegations are shown with the slashes not yet settled
+    let mut delegated_amount = stake.delegated_amount;
+    let delegations = DELEGATIONS
+        .prefix(grant_key)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (validator, mut delegation) = item?;
+            let validator = String::from_utf8(validator)?;
+            let slashed_amount =
+                settle_slashes(&mut delegation, &load_validator(deps, &env, &validator)?)?;
+            delegated_amount = delegated_amount.checked_sub(slashed_amount)?;
+            Ok(DelegationInfo {
+                validator,
+                amount: delegation.amount,
+            })
+        })
+        .collect::<StdResult<Vec<DelegationInfo>>>()?;
+
+    Ok(StakeResponse {
+        delegated_amount,
+        delegations,
+        unbondings: stake
+            .unbondings
+            .into_iter()
+            .map(|unbonding| UnbondingInfo {
+                amount: unbonding.amount,
+                release_time: unbonding.release_time,
+            })
+            .collect(),
+    })
+}
+
+/// amount of the vesting grant held by the staking module, bonded or unbonding
+pub fn staked_amount(storage: &dyn Storage, block_time: u64, grant_id: u64) -> StdResult<Uint128> {
+    match STAKES.may_load(storage, U64Key::new(grant_id))? {
+        Some(mut stake) => {
+            stake.release(block_time);
+            stake.staked_amount()
+        }
+        None => Ok(Uint128::zero()),
+    }
+}
+
+/// amount of the vesting grant returned from the unbondings released at block_time
+pub fn released_amount(
+    storage: &dyn Storage,
+    block_time: u64,
+    grant_id: u64,
+) -> StdResult<Uint128> {
+    let mut released_amount = Uint128::zero();
+    if let Some(stake) = STAKES.may_load(storage, U64Key::new(grant_id))? {
+        for unbonding in stake.unbondings.iter() {
+            if unbonding.release_time.u64() <= block_time {
+                released_amount = released_amount.checked_add(unbonding.amount)?;
+            }
+        }
+    }
+
+    Ok(released_amount)
+}
+
+/// Refuses to pay out released unbondings unless the balance of the contract covers the
+/// unclaimed and unstaked amount of every grant of the denom. The staking module may slash
+/// an unbonding, which is not reported to the contract, and the shortfall must not be paid
+/// out of the deposits of the other grants. `amount` is the payout of the calling operation,
+/// still counted in the unclaimed amount of the denom.
+pub fn assert_released_covered(
+    deps: DepsMut,
+    env: &Env,
+    denom: &str,
+    amount: Uint128,
+) -> Result<(), ContractError> {
+    let denom_key = denom_to_key(Denom::Native(denom.to_string()));
+    release_unbondings(deps.storage, &denom_key, env.block.time.seconds())?;
+
+    let summary = DENOM_SUMMARIES
+        .may_load(deps.storage, &denom_key)?
+        .unwrap_or_default();
+    let held_amount = summary
+        .unclaimed_amount
+        .checked_sub(summary.staked_amount)?;
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, denom)?
+        .amount;
+
+    // the part of the balance not held for the other grants
+    let available = balance.checked_add(amount)?.saturating_sub(held_amount);
+    if amount > available {
+        return Err(ContractError::InsufficientFunds { available });
+    }
+
+    Ok(())
+}
+
+/// remove the unbondings released at block_time from the staked amount of the denom
+fn release_unbondings(
+    storage: &mut dyn Storage,
+    denom_key: &str,
+    block_time: u64,
+) -> StdResult<()> {
+    let mut released: Vec<(u64, u64)> = vec![];
+    let mut released_amount = Uint128::zero();
+    for item in UNBONDING_QUEUE
+        .sub_prefix(denom_key)
+        .range(storage, None, None, Order::Ascending)
+    {
+        let (key, amount) = item?;
+        // the (release_time, grant_id) key with the length prefixed release_time
+        let release_time = u64::from_be_bytes(key[2..10].try_into().unwrap());
+        if release_time > block_time {
+            break;
+        }
+
+        let grant_id = u64::from_be_bytes(key[10..18].try_into().unwrap());
+        released.push((release_time, grant_id));
+        released_amount = released_amount.checked_add(amount)?;
+    }
+    if released.is_empty() {
+        return Ok(());
+    }
+
+    for (release_time, grant_id) in released.into_iter() {
+        UNBONDING_QUEUE.remove(
+            storage,
+            (denom_key, U64Key::new(release_time), U64Key::new(grant_id)),
+        );
+    }
+    DENOM_SUMMARIES.update(storage, denom_key, |summary| -> StdResult<_> {
+        let mut summary = summary.unwrap_or_default();
+        summary.staked_amount = summary.staked_amount.checked_sub(released_amount)?;
+        Ok(summary)
+    })?;
+
+    Ok(())
+}
+
+/// unclaimed amount of the vesting grant held by the contract,
+/// after the pending return of a terminated grant
+pub fn unstaked_amount(
+    storage: &dyn Storage,
+    block_time: u64,
+    account: &VestingAccount,
+) -> StdResult<Uint128> {
+    let staked_amount = staked_amount(storage, block_time, account.grant_id)?;
+    Ok(account
+        .vesting_amount
+        .checked_sub(account.claimed_amount)?
+        .saturating_sub(staked_amount))
+}
+
+/// part of the pending return of a terminated grant held by the contract,
+/// the unstaked tokens are returned before they are claimable
+pub fn returnable_amount(
+    storage: &dyn Storage,
+    block_time: u64,
+    account: &VestingAccount,
+) -> StdResult<Uint128> {
+    let staked_amount = staked_amount(storage, block_time, account.grant_id)?;
+    Ok(account
+        .vesting_amount
+        .checked_sub(account.claimed_amount)?
+        .checked_add(account.pending_return_amount)?
+        .saturating_sub(staked_amount)
+        .min(account.pending_return_amount))
+}
+
+/// validator with the slashes since the last staking action applied,
+/// detected by the real delegation of the contract falling below the recorded one
+fn load_validator(deps: Deps, env: &Env, validator: &str) -> StdResult<Validator> {
+    let mut validator_info = VALIDATORS
+        .may_load(deps.storage, validator)?
+        .unwrap_or_default();
+    if validator_info.delegated_amount.is_zero() {
+        return Ok(validator_info);
+    }
+
+    let delegated_amount = deps
+        .querier
+        .query_delegation(&env.contract.address, validator)?
+        .map(|delegation| delegation.amount.amount)
+        .unwrap_or_default();
+    if delegated_amount < validator_info.delegated_amount {
+        let slash_index = Uint128::new(validator_info.slash_index.numerator())
+            .multiply_ratio(delegated_amount, validator_info.delegated_amount);
+        validator_info.slash_index =
+            Decimal::from_ratio(slash_index, validator_info.slash_index.denominator());
+        validator_info.delegated_amount = delegated_amount;
+    }
+
+    Ok(validator_info)
+}
+
+/// lower the delegation by the slashes of the validator since the last settlement,
+/// returns the slashed amount
+fn settle_slashes(delegation: &mut Delegation, validator: &Validator) -> StdResult<Uint128> {
+    if delegation.slash_index == validator.slash_index {
+        return Ok(Uint128::zero());
+    }
+
+    let amount = delegation.amount.multiply_ratio(
+        validator.slash_index.numerator(),
+        delegation.slash_index.numerator(),
+    );
+    let slashed_amount = delegation.amount.checked_sub(amount)?;
+    delegation.amount = amount;
+    delegation.slash_index = validator.slash_index;
+
+    Ok(slashed_amount)
+}
+
+fn bonded_denom(deps: Deps, denom: &Denom) -> Result<String, ContractError> {
+    match denom {
+        Denom::Native(denom) if *denom == deps.querier.query_bonded_denom()? => {
+            Ok(denom.to_string())
+        }
+        _ => Err(ContractError::NotStakable {}),
+    }
+}
+
+fn reward_index(reward_indexes: &[(String, Decimal)], denom: &str) -> Decimal {
+    reward_indexes
+        .iter()
+        .find(|(reward_denom, _)| reward_denom == denom)
+        .map(|(_, index)| *index)
+        .unwrap_or_else(Decimal::zero)
+}
+
+fn set_reward_index(reward_indexes: &mut Vec<(String, Decimal)>, denom: &str, index: Decimal) {
+    match reward_indexes
+        .iter_mut()
+        .find(|(reward_denom, _)| reward_denom == denom)
+    {
+        Some(reward_index) => reward_index.1 = index,
+        None => reward_indexes.push((denom.to_string(), index)),
+    }
+}
@@ -1,21 +1,407 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::error::ContractError;
 use crate::msg::VestingSchedule;
-use cosmwasm_std::Uint128;
-use cw20::Denom;
-use cw_storage_plus::Map;
+use cosmwasm_std::{BlockInfo, Coin, Decimal, Order, StdResult, Storage, Uint128, Uint64};
+use cw20::{Denom, Expiration};
+use cw_storage_plus::{
+    Index, IndexList, IndexedMap, Item, Map, MultiIndex, SnapshotMap, Strategy, U64Key,
+};
+use serde_json::to_string;
 
-pub const VESTING_ACCOUNTS: Map<(&str, &str), VestingAccount> = Map::new("vesting_accounts");
+/// unbonding period of the chain in second unit, 21 days on Terra
+pub const DEFAULT_UNBONDING_PERIOD: u64 = 21 * 24 * 60 * 60;
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Config {
+    pub unbonding_period: u64,
+    // admin of the pause and the denom allowlist
+    pub admin: Option<String>,
+    // every state-changing operation except the admin operations is blocked while paused
+    #[serde(default)]
+    pub paused: bool,
+    // accepted vesting denoms, any denom is accepted when not set
+    pub allowed_denoms: Option<Vec<Denom>>,
+}
+
+type AddressIndex<'a> = MultiIndex<'a, (Vec<u8>, Vec<u8>, Vec<u8>), VestingAccount>;
+
+pub struct VestingAccountIndexes<'a> {
+    // index by (address, denom key), primary key is appended
+    pub address: AddressIndex<'a>,
+    // index by denom key, primary key is appended
+    pub denom: MultiIndex<'a, (Vec<u8>, Vec<u8>), VestingAccount>,
+    // index by master address, primary key is appended
+    pub master: MultiIndex<'a, (Vec<u8>, Vec<u8>), VestingAccount>,
+}
+
+impl<'a> IndexList<VestingAccount> for VestingAccountIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<VestingAccount>> + '_> {
+        let v: Vec<&dyn Index<VestingAccount>> = vec![&self.address, &self.denom, &self.master];
+        Box::new(v.into_iter())
+    }
+}
+
+/// vesting grants keyed by grant_id
+pub fn vesting_accounts<'a>() -> IndexedMap<'a, U64Key, VestingAccount, VestingAccountIndexes<'a>> {
+    let indexes = VestingAccountIndexes {
+        address: MultiIndex::new(
+            |v, pk| {
+                (
+                    v.address.clone().into_bytes(),
+                    denom_to_key(v.vesting_denom.clone()).into_bytes(),
+                    pk,
+                )
+            },
+            "grants",
+            "grants__address",
+        ),
+        denom: MultiIndex::new(
+            |v, pk| (denom_to_key(v.vesting_denom.clone()).into_bytes(), pk),
+            "grants",
+            "grants__denom",
+        ),
+        master: MultiIndex::new(
+            |v, pk| {
+                (
+                    v.master_address.clone().unwrap_or_default().into_bytes(),
+                    pk,
+                )
+            },
+            "grants",
+            "grants__master",
+        ),
+    };
+
+    IndexedMap::new("grants", indexes)
+}
+
+/// last issued grant_id
+pub const GRANT_SEQ: Item<u64> = Item::new("grant_seq");
+
+/// voting power (unvested plus unclaimed amount of all grants)
+/// snapshots keyed by (address, denom_key)
+pub const VOTING_POWERS: SnapshotMap<(&str, &str), Uint128> = SnapshotMap::new(
+    "voting_powers",
+    "voting_powers__checkpoints",
+    "voting_powers__changelog",
+    Strategy::EveryBlock,
+);
+
+/// transfer destinations waiting for master approval keyed by grant_id
+pub const PENDING_TRANSFERS: Map<U64Key, String> = Map::new("grant_pending_transfers");
+
+/// master_address proposals waiting for acceptance keyed by grant_id
+pub const PENDING_MASTERS: Map<U64Key, String> = Map::new("grant_pending_masters");
+
+/// claim operators keyed by (address, operator)
+pub const OPERATORS: Map<(&str, &str), Operator> = Map::new("operators");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Operator {
+    pub recipient: Option<String>,
+    pub expires: Expiration,
+}
+
+/// running totals keyed by denom_key
+pub const DENOM_SUMMARIES: Map<&str, DenomSummary> = Map::new("denom_summaries");
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct DenomSummary {
+    pub deposited_amount: Uint128,
+    pub claimed_amount: Uint128,
+    pub returned_amount: Uint128,
+    // lost to the slashing of the delegations
+    #[serde(default)]
+    pub slashed_amount: Uint128,
+    // unclaimed amount of the remaining grants, updated with their voting powers
+    #[serde(default)]
+    pub unclaimed_amount: Uint128,
+    // bonded and not yet released unbonding amount of the grants
+    #[serde(default)]
+    pub staked_amount: Uint128,
+}
+
+/// bonded and unbonding amounts of vesting grants keyed by grant_id
+pub const STAKES: Map<U64Key, Stake> = Map::new("grant_stakes");
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, JsonSchema, Debug)]
+pub struct Stake {
+    pub delegated_amount: Uint128,
+    pub unbondings: Vec<Unbonding>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Unbonding {
+    pub amount: Uint128,
+    pub release_time: Uint64,
+}
+
+impl Stake {
+    /// drop the unbondings released at block_time
+    pub fn release(&mut self, block_time: u64) {
+        self.unbondings
+            .retain(|unbonding| unbonding.release_time.u64() > block_time);
+    }
+
+    /// amount held by the staking module, bonded or unbonding
+    pub fn staked_amount(&self) -> StdResult<Uint128> {
+        let mut staked_amount = self.delegated_amount;
+        for unbonding in self.unbondings.iter() {
+            staked_amount = staked_amount.checked_add(unbonding.amount)?;
+        }
+
+        Ok(staked_amount)
+    }
+}
+
+/// unbonding amounts of all grants keyed by (denom_key, release_time, grant_id),
+/// removed from the staked_amount of the denom once released
+pub const UNBONDING_QUEUE: Map<(&str, U64Key, U64Key), Uint128> = Map::new("unbonding_queue");
+
+/// delegations of vesting grants keyed by (grant_id, validator)
+pub const DELEGATIONS: Map<(U64Key, &str), Delegation> = Map::new("grant_delegations");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Delegation {
+    pub amount: Uint128,
+    // reward indexes of the validator at the last settlement
+    pub reward_indexes: Vec<(String, Decimal)>,
+    // slash index of the validator at the last settlement
+    #[serde(default = "Decimal::one")]
+    pub slash_index: Decimal,
+}
+
+impl Default for Delegation {
+    fn default() -> Self {
+        Delegation {
+            amount: Uint128::zero(),
+            reward_indexes: vec![],
+            slash_index: Decimal::one(),
+        }
+    }
+}
+
+/// delegations of the contract keyed by validator
+pub const VALIDATORS: Map<&str, Validator> = Map::new("validators");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct Validator {
+    pub delegated_amount: Uint128,
+    // accumulated rewards per delegated token for each reward denom
+    pub reward_indexes: Vec<(String, Decimal)>,
+    // ratio of the delegated tokens left after the slashes
+    #[serde(default = "Decimal::one")]
+    pub slash_index: Decimal,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator {
+            delegated_amount: Uint128::zero(),
+            reward_indexes: vec![],
+            slash_index: Decimal::one(),
+        }
+    }
+}
+
+/// staking operation waiting for the rewards withdrawal reply
+pub const PENDING_STAKING: Item<PendingStaking> = Item::new("pending_staking");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PendingStaking {
+    pub grant_id: u64,
+    pub address: String,
+    pub denom: String,
+    pub validator: String,
+    pub action: StakingAction,
+    // contract balances before the rewards withdrawal
+    pub balances: Vec<Coin>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StakingAction {
+    Delegate { amount: Uint128 },
+    Undelegate { amount: Uint128 },
+    WithdrawRewards {},
+}
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct VestingAccount {
+    // not stored before 0.2.0, assigned on the migration
+    #[serde(default)]
+    pub grant_id: u64,
     pub master_address: Option<String>,
     pub address: String,
     pub vesting_denom: Denom,
     pub vesting_amount: Uint128,
     pub vesting_schedule: VestingSchedule,
     pub claimed_amount: Uint128,
+    // block time of the master termination, the vesting_amount is cut
+    // down to the amount vested at the termination
+    pub terminated_at: Option<Uint64>,
+    // unvested amount of the terminated grant which was still staked,
+    // returned to the return_address once undelegated and released
+    #[serde(default)]
+    pub pending_return_amount: Uint128,
+    #[serde(default)]
+    pub return_address: Option<String>,
+}
+
+impl VestingAccount {
+    /// vested amount at the given block, capped by the vesting_amount
+    /// which is lowered on the termination
+    pub fn vested_amount_at(&self, block: &BlockInfo) -> StdResult<Uint128> {
+        Ok(self
+            .vesting_schedule
+            .vested_amount_at(block)?
+            .min(self.vesting_amount))
+    }
+}
+
+/// save the vesting grant and snapshot the voting power of its owner at the given height
+pub fn save_vesting_account(
+    storage: &mut dyn Storage,
+    account: &VestingAccount,
+    height: u64,
+) -> StdResult<()> {
+    let key = U64Key::new(account.grant_id);
+    if let Some(old_account) = vesting_accounts().may_load(storage, key.clone())? {
+        update_voting_power(storage, &old_account, height, false)?;
+    }
+
+    vesting_accounts().save(storage, key, account)?;
+    update_voting_power(storage, account, height, true)
+}
+
+/// remove the vesting grant with its pending requests, stake and voting power
+pub fn remove_vesting_account(
+    storage: &mut dyn Storage,
+    grant_id: u64,
+    height: u64,
+) -> StdResult<()> {
+    let key = U64Key::new(grant_id);
+    let account = vesting_accounts().load(storage, key.clone())?;
+    vesting_accounts().remove(storage, key.clone())?;
+    update_voting_power(storage, &account, height, false)?;
+    PENDING_TRANSFERS.remove(storage, key.clone());
+    PENDING_MASTERS.remove(storage, key.clone());
+    STAKES.remove(storage, key);
+    Ok(())
+}
+
+/// add or subtract the voting power of the grant to the total of its owner
+/// and to the unclaimed amount of the denom
+fn update_voting_power(
+    storage: &mut dyn Storage,
+    account: &VestingAccount,
+    height: u64,
+    add: bool,
+) -> StdResult<()> {
+    let denom_key = denom_to_key(account.vesting_denom.clone());
+    let grant_power = account.vesting_amount.checked_sub(account.claimed_amount)?;
+    let unclaimed_amount = grant_power.checked_add(account.pending_return_amount)?;
+    VOTING_POWERS.update(
+        storage,
+        (account.address.as_str(), &denom_key),
+        height,
+        |power| -> StdResult<_> {
+            let power = power.unwrap_or_default();
+            if add {
+                Ok(power.checked_add(grant_power)?)
+            } else {
+                Ok(power.checked_sub(grant_power)?)
+            }
+        },
+    )?;
+    DENOM_SUMMARIES.update(storage, &denom_key, |summary| -> StdResult<_> {
+        let mut summary = summary.unwrap_or_default();
+        summary.unclaimed_amount = if add {
+            summary.unclaimed_amount.checked_add(unclaimed_amount)?
+        } else {
+            summary.unclaimed_amount.checked_sub(unclaimed_amount)?
+        };
+        Ok(summary)
+    })?;
+    Ok(())
+}
+
+/// issue a new grant_id
+pub fn next_grant_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let grant_id = GRANT_SEQ.may_load(storage)?.unwrap_or_default() + 1;
+    GRANT_SEQ.save(storage, &grant_id)?;
+    Ok(grant_id)
+}
+
+/// the given grant, or all grants of the address in the denom when grant_id is not given
+pub fn load_grants(
+    storage: &dyn Storage,
+    address: &str,
+    denom: &Denom,
+    grant_id: Option<u64>,
+) -> Result<Vec<VestingAccount>, ContractError> {
+    if grant_id.is_some() {
+        return Ok(vec![load_grant(storage, address, denom, grant_id)?]);
+    }
+
+    let grants = grants_of(storage, address, denom)?;
+    if grants.is_empty() {
+        return Err(ContractError::NotFound {
+            denom: to_string(denom).unwrap(),
+        });
+    }
+
+    Ok(grants)
+}
+
+/// all grants of the address in the denom ordered by grant_id
+pub fn grants_of(
+    storage: &dyn Storage,
+    address: &str,
+    denom: &Denom,
+) -> StdResult<Vec<VestingAccount>> {
+    vesting_accounts()
+        .idx
+        .address
+        .prefix((
+            address.as_bytes().to_vec(),
+            denom_to_key(denom.clone()).into_bytes(),
+        ))
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, account)| account))
+        .collect()
+}
+
+/// load the grant of the address in the denom,
+/// grant_id can be omitted when the address has only one grant in the denom
+pub fn load_grant(
+    storage: &dyn Storage,
+    address: &str,
+    denom: &Denom,
+    grant_id: Option<u64>,
+) -> Result<VestingAccount, ContractError> {
+    let not_found = || ContractError::NotFound {
+        denom: to_string(denom).unwrap(),
+    };
+
+    match grant_id {
+        Some(grant_id) => vesting_accounts()
+            .may_load(storage, U64Key::new(grant_id))?
+            .filter(|account| account.address == address && account.vesting_denom == *denom)
+            .ok_or_else(not_found),
+        None => {
+            let mut grants = grants_of(storage, address, denom)?;
+            match grants.len() {
+                0 => Err(not_found()),
+                1 => Ok(grants.remove(0)),
+                _ => Err(ContractError::GrantIdRequired {}),
+            }
+        }
+    }
 }
 
 pub fn denom_to_key(denom: Denom) -> String {
@@ -24,3 +410,68 @@ pub fn denom_to_key(denom: Denom) -> String {
         Denom::Native(denom) => format!("native-{}", denom),
     }
 }
+
+pub struct LegacyVestingAccountIndexes<'a> {
+    pub denom: MultiIndex<'a, (Vec<u8>, Vec<u8>), VestingAccount>,
+    pub master: MultiIndex<'a, (Vec<u8>, Vec<u8>), VestingAccount>,
+}
+
+impl<'a> IndexList<VestingAccount> for LegacyVestingAccountIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<VestingAccount>> + '_> {
+        let v: Vec<&dyn Index<VestingAccount>> = vec![&self.denom, &self.master];
+        Box::new(v.into_iter())
+    }
+}
+
+/// vesting accounts of the versions before 0.2.0 keyed by (address, denom_key),
+/// only read by the migration
+pub fn legacy_vesting_accounts<'a>(
+) -> IndexedMap<'a, (&'a str, &'a str), VestingAccount, LegacyVestingAccountIndexes<'a>> {
+    let indexes = LegacyVestingAccountIndexes {
+        denom: MultiIndex::new(
+            |v, pk| (denom_to_key(v.vesting_denom.clone()).into_bytes(), pk),
+            "vesting_accounts",
+            "vesting_accounts__denom",
+        ),
+        master: MultiIndex::new(
+            |v, pk| {
+                (
+                    v.master_address.clone().unwrap_or_default().into_bytes(),
+                    pk,
+                )
+            },
+            "vesting_accounts",
+            "vesting_accounts__master",
+        ),
+    };
+
+    IndexedMap::new("vesting_accounts", indexes)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStage {
+    /// Moving the (address, denom) keyed vesting accounts of the versions before 0.2.0 to grants
+    LegacyAccounts,
+    /// Rewriting the grants in the current schema and summing their voting powers
+    Grants,
+    /// Saving the summed voting powers as snapshots
+    VotingPowers,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct MigrationInfo {
+    pub stage: MigrationStage,
+    pub previous_version: String,
+    /// last grant rewritten in the Grants stage
+    pub last_grant_id: Option<u64>,
+    /// number of grants rewritten
+    pub accounts: u64,
+    pub completed: bool,
+}
+
+/// Set while the storage migration is in progress, the contract is not usable until it completes
+pub const MIGRATION: Item<MigrationInfo> = Item::new("migration");
+/// Voting powers summed over the rewritten grants keyed by (address, denom_key),
+/// saved as snapshots once all grants are rewritten
+pub const STAGED_VOTING_POWERS: Map<(&str, &str), Uint128> = Map::new("staged_voting_powers");
@@ -1,27 +1,343 @@
-use crate::contract::{execute, instantiate, query};
+use crate::contract::{execute, instantiate, migrate, query, reply};
+use crate::error::ContractError;
 use crate::msg::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, VestingAccountResponse, VestingData,
-    VestingSchedule,
+    ConfigResponse, Cw20HookMsg, DelegationInfo, DenomSummaryResponse, ExecuteMsg, InstantiateMsg,
+    MigrateMsg, OperatorInfo, OperatorsResponse, QueryMsg, StakeResponse, UnbondingInfo,
+    VestingAccountData, VestingAccountMsg, VestingAccountResponse, VestingAccountsResponse,
+    VestingData, VestingSchedule, VestingSimulation, VestingSimulationResponse,
+    VestingSummaryResponse, VotingPowerResponse,
 };
+use crate::staking::WITHDRAW_REWARDS_REPLY_ID;
+use crate::state::{vesting_accounts, VestingAccount};
 
 use cosmwasm_std::{
     from_binary,
-    testing::{mock_dependencies, mock_env, mock_info},
-    to_binary, Addr, Attribute, BankMsg, Coin, Response, StdError, SubMsg, Timestamp, Uint128,
-    WasmMsg,
+    testing::{mock_dependencies, mock_env, mock_info, MockQuerier, MOCK_CONTRACT_ADDR},
+    to_binary, Addr, Attribute, BankMsg, Binary, Coin, ContractResult, Deps, DistributionMsg, Env,
+    FullDelegation, Reply, Response, StakingMsg, StdError, Storage, SubMsg,
+    SubMsgExecutionResponse, Timestamp, Uint128, Uint64, WasmMsg,
 };
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom};
+use cw2::{get_contract_version, set_contract_version, ContractVersion};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Denom, Expiration};
+use cw_storage_plus::U64Key;
 
 #[test]
 fn proper_initialization() {
     let mut deps = mock_dependencies(&[]);
 
-    let msg = InstantiateMsg {};
+    let msg = InstantiateMsg {
+        unbonding_period: None,
+        admin: None,
+        allowed_denoms: None,
+    };
 
     let info = mock_info("addr0000", &[]);
 
     // we can just call .unwrap() to assert this was a success
     let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+    assert_eq!(
+        from_binary::<ConfigResponse>(
+            &query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()
+        )
+        .unwrap(),
+        ConfigResponse {
+            admin: Some("addr0000".to_string()),
+            unbonding_period: 21 * 24 * 60 * 60,
+            paused: false,
+            allowed_denoms: None,
+        }
+    );
+}
+
+#[test]
+fn pause_and_allowed_denoms() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: Some("admin0000".to_string()),
+            allowed_denoms: Some(vec![Denom::Native("uusd".to_string())]),
+        },
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+
+    // only the allowed denoms can be registered
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "ukrw")]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res.unwrap_err() {
+        ContractError::DenomNotAllowed { denom } => {
+            assert_eq!(denom, "{\"native\":\"ukrw\"}")
+        }
+        _ => panic!("should not enter"),
+    }
+
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg.clone()).unwrap();
+
+    // only admin can pause
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Pause {},
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::Pause {},
+    )
+    .unwrap();
+
+    // registration and claims are blocked while paused
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+    match res.unwrap_err() {
+        ContractError::Paused {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    env.block.time = Timestamp::from_seconds(105);
+    let claim_msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        claim_msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Paused {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // every other non-admin operation is blocked as well
+    let denom = Denom::Native("uusd".to_string());
+    for msg in vec![
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "addr0000".to_string(),
+            amount: Uint128::new(1000000u128),
+            msg: Binary::default(),
+        }),
+        ExecuteMsg::RegisterVestingAccounts {
+            vesting_accounts: vec![],
+        },
+        ExecuteMsg::RegisterVestingAccountsFrom {
+            token: "token0000".to_string(),
+            vesting_accounts: vec![],
+        },
+        ExecuteMsg::DeregisterVestingAccount {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+            vested_token_recipient: None,
+            left_vesting_token_recipient: None,
+        },
+        ExecuteMsg::TerminateVesting {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+            left_vesting_token_recipient: None,
+        },
+        ExecuteMsg::ApproveVestingAccountTransfer {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+        },
+        ExecuteMsg::ProposeNewMaster {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+            new_master_address: "master0001".to_string(),
+        },
+        ExecuteMsg::AcceptMaster {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+        },
+        ExecuteMsg::AmendVestingAccount {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+            end_time: None,
+        },
+        ExecuteMsg::TransferVestingAccount {
+            denom: denom.clone(),
+            grant_id: None,
+            new_address: "addr0002".to_string(),
+        },
+        ExecuteMsg::AuthorizeOperator {
+            operator: "operator0000".to_string(),
+            recipient: None,
+            expires: None,
+        },
+        ExecuteMsg::RevokeOperator {
+            operator: "operator0000".to_string(),
+        },
+        ExecuteMsg::Delegate {
+            denom: denom.clone(),
+            grant_id: None,
+            validator: "val0000".to_string(),
+            amount: Uint128::new(1000u128),
+        },
+        ExecuteMsg::Undelegate {
+            address: "addr0001".to_string(),
+            denom: denom.clone(),
+            grant_id: None,
+            validator: "val0000".to_string(),
+            amount: Uint128::new(1000u128),
+        },
+        ExecuteMsg::WithdrawRewards {
+            denom: denom.clone(),
+            grant_id: None,
+            validator: "val0000".to_string(),
+        },
+        ExecuteMsg::ClaimFor {
+            address: "addr0001".to_string(),
+            denoms: vec![denom.clone()],
+        },
+    ] {
+        let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+        match res.unwrap_err() {
+            ContractError::Paused {} => {}
+            _ => panic!("should not enter"),
+        }
+    }
+
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap();
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        claim_msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(500000u128, "uusd")],
+        })]
+    );
+
+    // lift the allowlist
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::UpdateAllowedDenoms {
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env, QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.allowed_denoms, None);
+    assert!(!res.paused);
+}
+
+#[test]
+fn update_unbonding_period() {
+    let mut deps = mock_dependencies(&[]);
+
+    // zero unbonding period would release the unbonding tokens at once
+    let res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: Some(0),
+            admin: None,
+            allowed_denoms: None,
+        },
+    );
+    match res.unwrap_err() {
+        ContractError::InvalidUnbondingPeriod {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: Some("admin0000".to_string()),
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    let msg = ExecuteMsg::UpdateUnbondingPeriod {
+        unbonding_period: 14 * 24 * 60 * 60,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::UpdateUnbondingPeriod {
+            unbonding_period: 0,
+        },
+    );
+    match res.unwrap_err() {
+        ContractError::InvalidUnbondingPeriod {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("admin0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            Attribute::new("action", "update_unbonding_period"),
+            Attribute::new("unbonding_period", "1209600"),
+        ]
+    );
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.unbonding_period, 14 * 24 * 60 * 60);
 }
 
 #[test]
@@ -31,7 +347,11 @@ fn register_vesting_account_with_native_token() {
         deps.as_mut(),
         mock_env(),
         mock_info("addr0000", &[]),
-        InstantiateMsg {},
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
     )
     .unwrap();
 
@@ -43,8 +363,8 @@ fn register_vesting_account_with_native_token() {
         master_address: None,
         address: "addr0001".to_string(),
         vesting_schedule: VestingSchedule::LinearVesting {
-            start_time: "100".to_string(),
-            end_time: "110".to_string(),
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
             vesting_amount: Uint128::zero(),
         },
     };
@@ -53,8 +373,8 @@ fn register_vesting_account_with_native_token() {
     let info = mock_info("addr0000", &[Coin::new(0u128, "uusd")]);
     let res = execute(deps.as_mut(), env.clone(), info, msg);
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => {
-            assert_eq!(msg, "assert(vesting_amount > 0)")
+        ContractError::InvalidSchedule { reason } => {
+            assert_eq!(reason, "assert(vesting_amount > 0)")
         }
         _ => panic!("should not enter"),
     }
@@ -64,8 +384,8 @@ fn register_vesting_account_with_native_token() {
         master_address: None,
         address: "addr0001".to_string(),
         vesting_schedule: VestingSchedule::LinearVesting {
-            start_time: "100".to_string(),
-            end_time: "110".to_string(),
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
             vesting_amount: Uint128::new(1000000u128),
         },
     };
@@ -74,7 +394,7 @@ fn register_vesting_account_with_native_token() {
     let info = mock_info("addr0000", &[]);
     let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => assert_eq!(msg, "must deposit only one type of token"),
+        ContractError::InvalidDeposit {} => {}
         _ => panic!("should not enter"),
     }
 
@@ -85,7 +405,7 @@ fn register_vesting_account_with_native_token() {
     );
     let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => assert_eq!(msg, "must deposit only one type of token"),
+        ContractError::InvalidDeposit {} => {}
         _ => panic!("should not enter"),
     }
 
@@ -93,8 +413,11 @@ fn register_vesting_account_with_native_token() {
     let info = mock_info("addr0000", &[Coin::new(10u128, "uusd")]);
     let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => {
-            assert_eq!(msg, "assert(deposit_amount == vesting_amount)")
+        ContractError::DepositMismatch { expected, got } => {
+            assert_eq!(
+                (expected, got),
+                (Uint128::new(1000000u128), Uint128::new(10u128))
+            )
         }
         _ => panic!("should not enter"),
     }
@@ -108,6 +431,7 @@ fn register_vesting_account_with_native_token() {
             ("action", "register_vesting_account"),
             ("master_address", "",),
             ("address", "addr0001"),
+            ("grant_id", "1"),
             ("vesting_denom", "{\"native\":\"uusd\"}"),
             ("vesting_amount", "1000000"),
         ]
@@ -122,6 +446,7 @@ fn register_vesting_account_with_native_token() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -131,13 +456,14 @@ fn register_vesting_account_with_native_token() {
         VestingAccountResponse {
             address: "addr0001".to_string(),
             vestings: vec![VestingData {
+                grant_id: 1,
                 master_address: None,
                 vesting_denom: Denom::Native("uusd".to_string()),
                 vesting_amount: Uint128::new(1000000),
                 vested_amount: Uint128::zero(),
                 vesting_schedule: VestingSchedule::LinearVesting {
-                    start_time: "100".to_string(),
-                    end_time: "110".to_string(),
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
                     vesting_amount: Uint128::new(1000000u128),
                 },
                 claimable_amount: Uint128::zero(),
@@ -153,7 +479,11 @@ fn register_vesting_account_with_cw20_token() {
         deps.as_mut(),
         mock_env(),
         mock_info("addr0000", &[]),
-        InstantiateMsg {},
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
     )
     .unwrap();
     let info = mock_info("token0000", &[]);
@@ -168,8 +498,8 @@ fn register_vesting_account_with_cw20_token() {
             master_address: None,
             address: "addr0001".to_string(),
             vesting_schedule: VestingSchedule::LinearVesting {
-                start_time: "100".to_string(),
-                end_time: "110".to_string(),
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
                 vesting_amount: Uint128::zero(),
             },
         })
@@ -179,8 +509,8 @@ fn register_vesting_account_with_cw20_token() {
     // invalid zero amount
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => {
-            assert_eq!(msg, "assert(vesting_amount > 0)")
+        ContractError::InvalidSchedule { reason } => {
+            assert_eq!(reason, "assert(vesting_amount > 0)")
         }
         _ => panic!("should not enter"),
     }
@@ -193,8 +523,8 @@ fn register_vesting_account_with_cw20_token() {
             master_address: None,
             address: "addr0001".to_string(),
             vesting_schedule: VestingSchedule::LinearVesting {
-                start_time: "100".to_string(),
-                end_time: "110".to_string(),
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
                 vesting_amount: Uint128::new(999000u128),
             },
         })
@@ -204,8 +534,11 @@ fn register_vesting_account_with_cw20_token() {
     // invalid amount
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => {
-            assert_eq!(msg, "assert(deposit_amount == vesting_amount)")
+        ContractError::DepositMismatch { expected, got } => {
+            assert_eq!(
+                (expected, got),
+                (Uint128::new(999000u128), Uint128::new(1000000u128))
+            )
         }
         _ => panic!("should not enter"),
     }
@@ -218,8 +551,8 @@ fn register_vesting_account_with_cw20_token() {
             master_address: None,
             address: "addr0001".to_string(),
             vesting_schedule: VestingSchedule::LinearVesting {
-                start_time: "100".to_string(),
-                end_time: "110".to_string(),
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
                 vesting_amount: Uint128::new(1000000u128),
             },
         })
@@ -234,6 +567,7 @@ fn register_vesting_account_with_cw20_token() {
             ("action", "register_vesting_account"),
             ("master_address", "",),
             ("address", "addr0001"),
+            ("grant_id", "1"),
             ("vesting_denom", "{\"cw20\":\"token0000\"}"),
             ("vesting_amount", "1000000"),
         ]
@@ -248,6 +582,7 @@ fn register_vesting_account_with_cw20_token() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -257,13 +592,14 @@ fn register_vesting_account_with_cw20_token() {
         VestingAccountResponse {
             address: "addr0001".to_string(),
             vestings: vec![VestingData {
+                grant_id: 1,
                 master_address: None,
                 vesting_denom: Denom::Cw20(Addr::unchecked("token0000")),
                 vesting_amount: Uint128::new(1000000),
                 vested_amount: Uint128::zero(),
                 vesting_schedule: VestingSchedule::LinearVesting {
-                    start_time: "100".to_string(),
-                    end_time: "110".to_string(),
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
                     vesting_amount: Uint128::new(1000000u128),
                 },
                 claimable_amount: Uint128::zero(),
@@ -279,7 +615,11 @@ fn claim_native() {
         deps.as_mut(),
         mock_env(),
         mock_info("addr0000", &[]),
-        InstantiateMsg {},
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
     )
     .unwrap();
 
@@ -292,8 +632,8 @@ fn claim_native() {
         master_address: None,
         address: "addr0001".to_string(),
         vesting_schedule: VestingSchedule::LinearVesting {
-            start_time: "100".to_string(),
-            end_time: "110".to_string(),
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
             vesting_amount: Uint128::new(1000000u128),
         },
     };
@@ -310,23 +650,24 @@ fn claim_native() {
             Denom::Native("ukrw".to_string()),
             Denom::Native("uusd".to_string()),
         ],
+        grant_ids: None,
         recipient: None,
+        msg: None,
     };
 
     let info = mock_info("addr0001", &[]);
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => assert_eq!(
-            msg,
-            "vesting entry is not found for denom {\"native\":\"ukrw\"}"
-        ),
+        ContractError::NotFound { denom } => assert_eq!(denom, "{\"native\":\"ukrw\"}"),
         _ => panic!("should not enter"),
     }
 
     // valid claim
     let msg = ExecuteMsg::Claim {
         denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
         recipient: None,
+        msg: None,
     };
 
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
@@ -361,6 +702,7 @@ fn claim_native() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -370,13 +712,14 @@ fn claim_native() {
         VestingAccountResponse {
             address: "addr0001".to_string(),
             vestings: vec![VestingData {
+                grant_id: 1,
                 master_address: None,
                 vesting_denom: Denom::Native("uusd".to_string()),
                 vesting_amount: Uint128::new(1000000),
                 vested_amount: Uint128::new(500000),
                 vesting_schedule: VestingSchedule::LinearVesting {
-                    start_time: "100".to_string(),
-                    end_time: "110".to_string(),
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
                     vesting_amount: Uint128::new(1000000u128),
                 },
                 claimable_amount: Uint128::zero(),
@@ -419,6 +762,7 @@ fn claim_native() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -439,7 +783,11 @@ fn claim_cw20() {
         deps.as_mut(),
         mock_env(),
         mock_info("addr0000", &[]),
-        InstantiateMsg {},
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
     )
     .unwrap();
 
@@ -455,8 +803,8 @@ fn claim_cw20() {
             master_address: None,
             address: "addr0001".to_string(),
             vesting_schedule: VestingSchedule::LinearVesting {
-                start_time: "100".to_string(),
-                end_time: "110".to_string(),
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
                 vesting_amount: Uint128::new(1000000u128),
             },
         })
@@ -476,23 +824,24 @@ fn claim_cw20() {
             Denom::Cw20(Addr::unchecked("token0002")),
             Denom::Cw20(Addr::unchecked("token0001")),
         ],
+        grant_ids: None,
         recipient: None,
+        msg: None,
     };
 
     let info = mock_info("addr0001", &[]);
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
     match res.unwrap_err() {
-        StdError::GenericErr { msg, .. } => assert_eq!(
-            msg,
-            "vesting entry is not found for denom {\"cw20\":\"token0002\"}"
-        ),
+        ContractError::NotFound { denom } => assert_eq!(denom, "{\"cw20\":\"token0002\"}"),
         _ => panic!("should not enter"),
     }
 
     // valid claim
     let msg = ExecuteMsg::Claim {
         denoms: vec![Denom::Cw20(Addr::unchecked("token0001"))],
+        grant_ids: None,
         recipient: None,
+        msg: None,
     };
 
     let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
@@ -529,6 +878,7 @@ fn claim_cw20() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -538,13 +888,14 @@ fn claim_cw20() {
         VestingAccountResponse {
             address: "addr0001".to_string(),
             vestings: vec![VestingData {
+                grant_id: 1,
                 master_address: None,
                 vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
                 vesting_amount: Uint128::new(1000000),
                 vested_amount: Uint128::new(500000),
                 vesting_schedule: VestingSchedule::LinearVesting {
-                    start_time: "100".to_string(),
-                    end_time: "110".to_string(),
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
                     vesting_amount: Uint128::new(1000000u128),
                 },
                 claimable_amount: Uint128::zero(),
@@ -589,6 +940,7 @@ fn claim_cw20() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -609,7 +961,11 @@ fn query_vesting_account() {
         deps.as_mut(),
         mock_env(),
         mock_info("addr0000", &[]),
-        InstantiateMsg {},
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
     )
     .unwrap();
 
@@ -622,8 +978,8 @@ fn query_vesting_account() {
         master_address: None,
         address: "addr0001".to_string(),
         vesting_schedule: VestingSchedule::LinearVesting {
-            start_time: "100".to_string(),
-            end_time: "110".to_string(),
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
             vesting_amount: Uint128::new(1000000u128),
         },
     };
@@ -638,8 +994,8 @@ fn query_vesting_account() {
             master_address: None,
             address: "addr0001".to_string(),
             vesting_schedule: VestingSchedule::LinearVesting {
-                start_time: "100".to_string(),
-                end_time: "110".to_string(),
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
                 vesting_amount: Uint128::new(1000000u128),
             },
         })
@@ -662,6 +1018,7 @@ fn query_vesting_account() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: None,
                 },
             )
@@ -672,25 +1029,27 @@ fn query_vesting_account() {
             address: "addr0001".to_string(),
             vestings: vec![
                 VestingData {
+                    grant_id: 1,
                     master_address: None,
-                    vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
+                    vesting_denom: Denom::Native("uusd".to_string()),
                     vesting_amount: Uint128::new(1000000),
                     vested_amount: Uint128::new(500000),
                     vesting_schedule: VestingSchedule::LinearVesting {
-                        start_time: "100".to_string(),
-                        end_time: "110".to_string(),
+                        start_time: Uint64::new(100),
+                        end_time: Uint64::new(110),
                         vesting_amount: Uint128::new(1000000u128),
                     },
                     claimable_amount: Uint128::new(500000),
                 },
                 VestingData {
+                    grant_id: 2,
                     master_address: None,
-                    vesting_denom: Denom::Native("uusd".to_string()),
+                    vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
                     vesting_amount: Uint128::new(1000000),
                     vested_amount: Uint128::new(500000),
                     vesting_schedule: VestingSchedule::LinearVesting {
-                        start_time: "100".to_string(),
-                        end_time: "110".to_string(),
+                        start_time: Uint64::new(100),
+                        end_time: Uint64::new(110),
                         vesting_amount: Uint128::new(1000000u128),
                     },
                     claimable_amount: Uint128::new(500000),
@@ -708,6 +1067,7 @@ fn query_vesting_account() {
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
                     start_after: None,
+                    start_after_grant: None,
                     limit: Some(1),
                 },
             )
@@ -717,13 +1077,14 @@ fn query_vesting_account() {
         VestingAccountResponse {
             address: "addr0001".to_string(),
             vestings: vec![VestingData {
+                grant_id: 1,
                 master_address: None,
-                vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
+                vesting_denom: Denom::Native("uusd".to_string()),
                 vesting_amount: Uint128::new(1000000),
                 vested_amount: Uint128::new(500000),
                 vesting_schedule: VestingSchedule::LinearVesting {
-                    start_time: "100".to_string(),
-                    end_time: "110".to_string(),
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
                     vesting_amount: Uint128::new(1000000u128),
                 },
                 claimable_amount: Uint128::new(500000),
@@ -736,10 +1097,11 @@ fn query_vesting_account() {
         from_binary::<VestingAccountResponse>(
             &query(
                 deps.as_ref(),
-                env,
+                env.clone(),
                 QueryMsg::VestingAccount {
                     address: "addr0001".to_string(),
-                    start_after: Some(Denom::Cw20(Addr::unchecked("token0001"))),
+                    start_after: Some(Denom::Native("uusd".to_string())),
+                    start_after_grant: Some(1),
                     limit: Some(1),
                 },
             )
@@ -749,17 +1111,3106 @@ fn query_vesting_account() {
         VestingAccountResponse {
             address: "addr0001".to_string(),
             vestings: vec![VestingData {
+                grant_id: 2,
                 master_address: None,
-                vesting_denom: Denom::Native("uusd".to_string()),
+                vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
                 vesting_amount: Uint128::new(1000000),
                 vested_amount: Uint128::new(500000),
                 vesting_schedule: VestingSchedule::LinearVesting {
-                    start_time: "100".to_string(),
-                    end_time: "110".to_string(),
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
                     vesting_amount: Uint128::new(1000000u128),
                 },
                 claimable_amount: Uint128::new(500000),
             }],
         }
     );
+
+    // a denom alone skips all of its grants
+    assert_eq!(
+        from_binary::<VestingAccountResponse>(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::VestingAccount {
+                    address: "addr0001".to_string(),
+                    start_after: Some(Denom::Native("uusd".to_string())),
+                    start_after_grant: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap()
+        .vestings
+        .iter()
+        .map(|vesting| vesting.grant_id)
+        .collect::<Vec<u64>>(),
+        vec![2]
+    );
+}
+
+#[test]
+fn register_and_claim_with_cliff() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    // cliff after end time
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVestingWithCliff {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            cliff_time: Uint64::new(111),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res.unwrap_err() {
+        ContractError::InvalidSchedule { reason } => {
+            assert_eq!(reason, "assert(start_time <= cliff_time <= end_time)")
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // valid cliff
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVestingWithCliff {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            cliff_time: Uint64::new(105),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // nothing to claim before the cliff
+    env.block.time = Timestamp::from_seconds(104);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg.clone()).unwrap();
+    assert_eq!(res.messages, vec![]);
+
+    // accrued amount is unlocked at the cliff
+    env.block.time = Timestamp::from_seconds(105);
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::new(500000u128),
+            }],
+        }),]
+    );
+}
+
+#[test]
+fn register_and_claim_custom_vesting() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    // unordered tranches
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::CustomVesting {
+            tranches: vec![
+                (Uint64::new(100), Uint128::new(100000u128)),
+                (Uint64::new(120), Uint128::new(400000u128)),
+                (Uint64::new(110), Uint128::new(500000u128)),
+            ],
+        },
+    };
+
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res.unwrap_err() {
+        ContractError::InvalidSchedule { reason } => {
+            assert_eq!(reason, "assert(tranche times are strictly increasing)")
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // tranches do not sum to the deposit
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::CustomVesting {
+            tranches: vec![
+                (Uint64::new(100), Uint128::new(100000u128)),
+                (Uint64::new(110), Uint128::new(400000u128)),
+            ],
+        },
+    };
+
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res.unwrap_err() {
+        ContractError::DepositMismatch { expected, got } => {
+            assert_eq!(
+                (expected, got),
+                (Uint128::new(500000u128), Uint128::new(1000000u128))
+            )
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // valid tranches
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::CustomVesting {
+            tranches: vec![
+                (Uint64::new(100), Uint128::new(100000u128)),
+                (Uint64::new(110), Uint128::new(400000u128)),
+                (Uint64::new(120), Uint128::new(500000u128)),
+            ],
+        },
+    };
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // first and second tranches are unlocked
+    env.block.time = Timestamp::from_seconds(115);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+
+    let info = mock_info("addr0001", &[]);
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::new(500000u128),
+            }],
+        }),]
+    );
+}
+
+#[test]
+fn register_vesting_accounts() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let vesting_accounts = vec![
+        VestingAccountMsg {
+            master_address: None,
+            address: "addr0001".to_string(),
+            vesting_schedule: VestingSchedule::LinearVesting {
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
+                vesting_amount: Uint128::new(1000000u128),
+            },
+        },
+        VestingAccountMsg {
+            master_address: Some("addr0000".to_string()),
+            address: "addr0002".to_string(),
+            vesting_schedule: VestingSchedule::PeriodicVesting {
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
+                vesting_interval: Uint64::new(5),
+                amount: Uint128::new(100000u128),
+            },
+        },
+    ];
+
+    // invalid deposit amount
+    let msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vesting_accounts.clone(),
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg);
+    match res.unwrap_err() {
+        ContractError::DepositMismatch { expected, got } => {
+            assert_eq!(
+                (expected, got),
+                (Uint128::new(1300000u128), Uint128::new(1000000u128))
+            )
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // valid native deposit
+    let msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vesting_accounts.clone(),
+    };
+    let info = mock_info("addr0000", &[Coin::new(1300000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "register_vesting_accounts"),
+            ("master_address", ""),
+            ("address", "addr0001"),
+            ("grant_id", "1"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+            ("vesting_amount", "1000000"),
+            ("master_address", "addr0000"),
+            ("address", "addr0002"),
+            ("grant_id", "2"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+            ("vesting_amount", "300000"),
+        ]
+    );
+
+    // valid cw20 deposit
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::new(1300000u128),
+        msg: to_binary(&Cw20HookMsg::RegisterVestingAccounts { vesting_accounts }).unwrap(),
+    });
+    let info = mock_info("token0001", &[]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // half claimable
+    env.block.time = Timestamp::from_seconds(105);
+    assert_eq!(
+        from_binary::<VestingAccountResponse>(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::VestingAccount {
+                    address: "addr0002".to_string(),
+                    start_after: None,
+                    start_after_grant: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountResponse {
+            address: "addr0002".to_string(),
+            vestings: vec![
+                VestingData {
+                    grant_id: 2,
+                    master_address: Some("addr0000".to_string()),
+                    vesting_denom: Denom::Native("uusd".to_string()),
+                    vesting_amount: Uint128::new(300000),
+                    vested_amount: Uint128::new(200000),
+                    vesting_schedule: VestingSchedule::PeriodicVesting {
+                        start_time: Uint64::new(100),
+                        end_time: Uint64::new(110),
+                        vesting_interval: Uint64::new(5),
+                        amount: Uint128::new(100000u128),
+                    },
+                    claimable_amount: Uint128::new(200000),
+                },
+                VestingData {
+                    grant_id: 4,
+                    master_address: Some("addr0000".to_string()),
+                    vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
+                    vesting_amount: Uint128::new(300000),
+                    vested_amount: Uint128::new(200000),
+                    vesting_schedule: VestingSchedule::PeriodicVesting {
+                        start_time: Uint64::new(100),
+                        end_time: Uint64::new(110),
+                        vesting_interval: Uint64::new(5),
+                        amount: Uint128::new(100000u128),
+                    },
+                    claimable_amount: Uint128::new(200000),
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn register_vesting_accounts_from_allowance() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let vesting_schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+    let msg = ExecuteMsg::RegisterVestingAccountsFrom {
+        token: "token0001".to_string(),
+        vesting_accounts: vec![
+            VestingAccountMsg {
+                master_address: None,
+                address: "addr0001".to_string(),
+                vesting_schedule: vesting_schedule.clone(),
+            },
+            VestingAccountMsg {
+                master_address: Some("addr0000".to_string()),
+                address: "addr0002".to_string(),
+                vesting_schedule,
+            },
+        ],
+    };
+
+    // no native deposit is accepted
+    let info = mock_info("addr0000", &[Coin::new(2000000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg.clone());
+    match res.unwrap_err() {
+        ContractError::InvalidDeposit {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // the sum of the vesting amounts is pulled from the allowance
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(WasmMsg::Execute {
+            contract_addr: "token0001".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: "addr0000".to_string(),
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::new(2000000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        })]
+    );
+
+    let msg = QueryMsg::VestingAccountsByDenom {
+        denom: Denom::Cw20(Addr::unchecked("token0001")),
+        start_after: None,
+        limit: None,
+    };
+    let res: VestingAccountsResponse =
+        from_binary(&query(deps.as_ref(), env, msg).unwrap()).unwrap();
+    assert_eq!(
+        res.vesting_accounts
+            .iter()
+            .map(|data| data.address.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["addr0001", "addr0002"]
+    );
+}
+
+#[test]
+fn query_vesting_accounts() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let vesting_schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+
+    // native vestings
+    let msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![
+            VestingAccountMsg {
+                master_address: Some("master0000".to_string()),
+                address: "addr0001".to_string(),
+                vesting_schedule: vesting_schedule.clone(),
+            },
+            VestingAccountMsg {
+                master_address: Some("master0001".to_string()),
+                address: "addr0002".to_string(),
+                vesting_schedule: vesting_schedule.clone(),
+            },
+        ],
+    };
+    let info = mock_info("addr0000", &[Coin::new(2000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // cw20 vesting
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::new(1000000u128),
+        msg: to_binary(&Cw20HookMsg::RegisterVestingAccount {
+            master_address: Some("master0000".to_string()),
+            address: "addr0001".to_string(),
+            vesting_schedule: vesting_schedule.clone(),
+        })
+        .unwrap(),
+    });
+    let info = mock_info("token0001", &[]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // half claimable
+    env.block.time = Timestamp::from_seconds(105);
+
+    let vesting_data = |grant_id: u64,
+                        address: &str,
+                        master_address: &str,
+                        vesting_denom: Denom| VestingAccountData {
+        address: address.to_string(),
+        vesting: VestingData {
+            grant_id,
+            master_address: Some(master_address.to_string()),
+            vesting_denom,
+            vesting_amount: Uint128::new(1000000),
+            vested_amount: Uint128::new(500000),
+            vesting_schedule: vesting_schedule.clone(),
+            claimable_amount: Uint128::new(500000),
+        },
+    };
+
+    // query all accounts
+    assert_eq!(
+        from_binary::<VestingAccountsResponse>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VestingAccounts {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountsResponse {
+            vesting_accounts: vec![
+                vesting_data(
+                    1,
+                    "addr0001",
+                    "master0000",
+                    Denom::Native("uusd".to_string())
+                ),
+                vesting_data(
+                    2,
+                    "addr0002",
+                    "master0001",
+                    Denom::Native("uusd".to_string())
+                ),
+                vesting_data(
+                    3,
+                    "addr0001",
+                    "master0000",
+                    Denom::Cw20(Addr::unchecked("token0001"))
+                ),
+            ],
+        }
+    );
+
+    // query all accounts after the first one
+    assert_eq!(
+        from_binary::<VestingAccountsResponse>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VestingAccounts {
+                    start_after: Some(1),
+                    limit: Some(1),
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountsResponse {
+            vesting_accounts: vec![vesting_data(
+                2,
+                "addr0002",
+                "master0001",
+                Denom::Native("uusd".to_string())
+            )],
+        }
+    );
+
+    // query accounts by denom
+    assert_eq!(
+        from_binary::<VestingAccountsResponse>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VestingAccountsByDenom {
+                    denom: Denom::Native("uusd".to_string()),
+                    start_after: Some(1),
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountsResponse {
+            vesting_accounts: vec![vesting_data(
+                2,
+                "addr0002",
+                "master0001",
+                Denom::Native("uusd".to_string())
+            )],
+        }
+    );
+
+    // query accounts by master
+    assert_eq!(
+        from_binary::<VestingAccountsResponse>(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::VestingAccountsByMaster {
+                    master_address: "master0000".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountsResponse {
+            vesting_accounts: vec![
+                vesting_data(
+                    1,
+                    "addr0001",
+                    "master0000",
+                    Denom::Native("uusd".to_string())
+                ),
+                vesting_data(
+                    3,
+                    "addr0001",
+                    "master0000",
+                    Denom::Cw20(Addr::unchecked("token0001"))
+                ),
+            ],
+        }
+    );
+}
+
+#[test]
+fn query_denom_summary() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let vesting_schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+
+    let msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![
+            VestingAccountMsg {
+                master_address: None,
+                address: "addr0001".to_string(),
+                vesting_schedule: vesting_schedule.clone(),
+            },
+            VestingAccountMsg {
+                master_address: Some("addr0000".to_string()),
+                address: "addr0002".to_string(),
+                vesting_schedule,
+            },
+        ],
+    };
+    let info = mock_info("addr0000", &[Coin::new(2000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // claim 20%
+    env.block.time = Timestamp::from_seconds(102);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    // deregister at 50%
+    env.block.time = Timestamp::from_seconds(105);
+    let msg = ExecuteMsg::DeregisterVestingAccount {
+        address: "addr0002".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        vested_token_recipient: None,
+        left_vesting_token_recipient: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    assert_eq!(
+        from_binary::<DenomSummaryResponse>(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::DenomSummary {
+                    denom: Denom::Native("uusd".to_string()),
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        DenomSummaryResponse {
+            denom: Denom::Native("uusd".to_string()),
+            deposited_amount: Uint128::new(2000000),
+            claimed_amount: Uint128::new(700000),
+            returned_amount: Uint128::new(500000),
+            slashed_amount: Uint128::zero(),
+            unclaimed_amount: Uint128::new(800000),
+            locked_amount: Uint128::new(500000),
+        }
+    );
+}
+
+#[test]
+fn transfer_vesting_account() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let vesting_schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+
+    let msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![
+            VestingAccountMsg {
+                master_address: None,
+                address: "addr0001".to_string(),
+                vesting_schedule: vesting_schedule.clone(),
+            },
+            VestingAccountMsg {
+                master_address: Some("addr0000".to_string()),
+                address: "addr0002".to_string(),
+                vesting_schedule: vesting_schedule.clone(),
+            },
+        ],
+    };
+    let info = mock_info("addr0000", &[Coin::new(2000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // claim half before transfer
+    env.block.time = Timestamp::from_seconds(105);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    // cannot transfer to itself
+    let msg = ExecuteMsg::TransferVestingAccount {
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_address: "addr0001".to_string(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::CannotSetOwnAccount {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // cannot transfer to an invalid address
+    let msg = ExecuteMsg::TransferVestingAccount {
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_address: "a".to_string(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::Std(StdError::GenericErr { .. }) => {}
+        _ => panic!("should not enter"),
+    }
+
+    // transfer without master
+    let msg = ExecuteMsg::TransferVestingAccount {
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_address: "addr0003".to_string(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "transfer_vesting_account"),
+            ("address", "addr0001"),
+            ("grant_id", "1"),
+            ("new_address", "addr0003"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+        ]
+    );
+
+    assert_eq!(
+        from_binary::<VestingAccountResponse>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VestingAccount {
+                    address: "addr0003".to_string(),
+                    start_after: None,
+                    start_after_grant: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountResponse {
+            address: "addr0003".to_string(),
+            vestings: vec![VestingData {
+                grant_id: 1,
+                master_address: None,
+                vesting_denom: Denom::Native("uusd".to_string()),
+                vesting_amount: Uint128::new(1000000),
+                vested_amount: Uint128::new(500000),
+                vesting_schedule: vesting_schedule.clone(),
+                claimable_amount: Uint128::zero(),
+            }],
+        }
+    );
+
+    // a master proposal made before the transfer follows the grant
+    let msg = ExecuteMsg::ProposeNewMaster {
+        address: "addr0002".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_master_address: "master0001".to_string(),
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // transfer with master is pending until approved
+    let msg = ExecuteMsg::TransferVestingAccount {
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_address: "addr0004".to_string(),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0002", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "request_vesting_account_transfer"),
+            ("address", "addr0002"),
+            ("grant_id", "2"),
+            ("new_address", "addr0004"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+        ]
+    );
+
+    // only master can approve
+    let msg = ExecuteMsg::ApproveVestingAccountTransfer {
+        address: "addr0002".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0002", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the proposal can no longer be accepted at the previous address
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0001", &[]),
+        ExecuteMsg::AcceptMaster {
+            address: "addr0002".to_string(),
+            denom: Denom::Native("uusd".to_string()),
+            grant_id: None,
+        },
+    );
+    assert!(res.is_err());
+
+    assert_eq!(
+        from_binary::<VestingAccountsResponse>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::VestingAccountsByMaster {
+                    master_address: "addr0000".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountsResponse {
+            vesting_accounts: vec![VestingAccountData {
+                address: "addr0004".to_string(),
+                vesting: VestingData {
+                    grant_id: 2,
+                    master_address: Some("addr0000".to_string()),
+                    vesting_denom: Denom::Native("uusd".to_string()),
+                    vesting_amount: Uint128::new(1000000),
+                    vested_amount: Uint128::new(500000),
+                    vesting_schedule,
+                    claimable_amount: Uint128::new(500000),
+                },
+            }],
+        }
+    );
+
+    // but it is still pending at the new address
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("master0001", &[]),
+        ExecuteMsg::AcceptMaster {
+            address: "addr0004".to_string(),
+            denom: Denom::Native("uusd".to_string()),
+            grant_id: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "accept_master"),
+            ("address", "addr0004"),
+            ("grant_id", "2"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+            ("master_address", "master0001"),
+        ]
+    );
+}
+
+#[test]
+fn rotate_master_address() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: Some("master0000".to_string()),
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // only master can propose
+    let msg = ExecuteMsg::ProposeNewMaster {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_master_address: "master0001".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // cannot propose an invalid address
+    let invalid_msg = ExecuteMsg::ProposeNewMaster {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        new_master_address: "a".to_string(),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        invalid_msg,
+    );
+    match res.unwrap_err() {
+        ContractError::Std(StdError::GenericErr { .. }) => {}
+        _ => panic!("should not enter"),
+    }
+
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg,
+    )
+    .unwrap();
+
+    // only proposed master can accept
+    let msg = ExecuteMsg::AcceptMaster {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0001", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "accept_master"),
+            ("address", "addr0001"),
+            ("grant_id", "1"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+            ("master_address", "master0001"),
+        ]
+    );
+
+    // previous master lost the control
+    let msg = ExecuteMsg::DeregisterVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        vested_token_recipient: None,
+        left_vesting_token_recipient: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let _ = execute(deps.as_mut(), env, mock_info("master0001", &[]), msg).unwrap();
+}
+
+#[test]
+fn amend_vesting_account() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: Some("addr0000".to_string()),
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::new(1000000u128),
+        msg: to_binary(&Cw20HookMsg::RegisterVestingAccount {
+            master_address: Some("addr0000".to_string()),
+            address: "addr0001".to_string(),
+            vesting_schedule: VestingSchedule::PeriodicVesting {
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
+                vesting_interval: Uint64::new(10),
+                amount: Uint128::new(500000u128),
+            },
+        })
+        .unwrap(),
+    });
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("token0001", &[]), msg).unwrap();
+
+    // claim half
+    env.block.time = Timestamp::from_seconds(105);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    // cannot take back the claimed amount
+    let msg = ExecuteMsg::AmendVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        end_time: Some(Uint64::new(120)),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::InvalidSchedule { reason } => {
+            assert_eq!(reason, "assert(vested_amount >= claimed_amount)")
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // extend and top up
+    let msg = ExecuteMsg::AmendVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        end_time: Some(Uint64::new(120)),
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "amend_vesting_account"),
+            ("address", "addr0001"),
+            ("grant_id", "1"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+            ("vesting_amount", "2000000"),
+            ("top_up_amount", "1000000"),
+        ]
+    );
+
+    // periodic vesting must be topped up by whole intervals
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::new(400000u128),
+        msg: to_binary(&Cw20HookMsg::AmendVestingAccount {
+            address: "addr0001".to_string(),
+            grant_id: None,
+            end_time: Some(Uint64::new(120)),
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), env.clone(), mock_info("token0001", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::DepositMismatch { expected, got } => {
+            assert_eq!(
+                (expected, got),
+                (Uint128::new(500000u128), Uint128::new(400000u128))
+            )
+        }
+        _ => panic!("should not enter"),
+    }
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::new(500000u128),
+        msg: to_binary(&Cw20HookMsg::AmendVestingAccount {
+            address: "addr0001".to_string(),
+            grant_id: None,
+            end_time: Some(Uint64::new(120)),
+        })
+        .unwrap(),
+    });
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("token0001", &[]), msg).unwrap();
+
+    assert_eq!(
+        from_binary::<VestingAccountResponse>(
+            &query(
+                deps.as_ref(),
+                env,
+                QueryMsg::VestingAccount {
+                    address: "addr0001".to_string(),
+                    start_after: None,
+                    start_after_grant: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        VestingAccountResponse {
+            address: "addr0001".to_string(),
+            vestings: vec![
+                VestingData {
+                    grant_id: 1,
+                    master_address: Some("addr0000".to_string()),
+                    vesting_denom: Denom::Native("uusd".to_string()),
+                    vesting_amount: Uint128::new(2000000),
+                    vested_amount: Uint128::new(500000),
+                    vesting_schedule: VestingSchedule::LinearVesting {
+                        start_time: Uint64::new(100),
+                        end_time: Uint64::new(120),
+                        vesting_amount: Uint128::new(2000000u128),
+                    },
+                    claimable_amount: Uint128::zero(),
+                },
+                VestingData {
+                    grant_id: 2,
+                    master_address: Some("addr0000".to_string()),
+                    vesting_denom: Denom::Cw20(Addr::unchecked("token0001")),
+                    vesting_amount: Uint128::new(1500000),
+                    vested_amount: Uint128::new(500000),
+                    vesting_schedule: VestingSchedule::PeriodicVesting {
+                        start_time: Uint64::new(100),
+                        end_time: Uint64::new(120),
+                        vesting_interval: Uint64::new(10),
+                        amount: Uint128::new(500000u128),
+                    },
+                    claimable_amount: Uint128::new(500000),
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn terminate_vesting() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: Some("addr0000".to_string()),
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    env.block.time = Timestamp::from_seconds(104);
+    let msg = ExecuteMsg::TerminateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        left_vesting_token_recipient: None,
+    };
+
+    // only master can terminate
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // the unvested amount is returned without paying out the vested amount
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin::new(600000u128, "uusd")],
+        })]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "terminate_vesting"),
+            ("address", "addr0001"),
+            ("grant_id", "1"),
+            ("vesting_denom", "{\"native\":\"uusd\"}"),
+            ("vested_amount", "400000"),
+            ("left_vesting_amount", "600000"),
+            ("pending_return_amount", "0"),
+        ]
+    );
+
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::AlreadyTerminated {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // the vesting stops at the termination
+    env.block.time = Timestamp::from_seconds(110);
+    let msg = QueryMsg::VestingSummary {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+    };
+    assert_eq!(
+        from_binary::<VestingSummaryResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap())
+            .unwrap(),
+        VestingSummaryResponse {
+            address: "addr0001".to_string(),
+            vesting_denom: Denom::Native("uusd".to_string()),
+            grant_ids: vec![1],
+            vesting_amount: Uint128::new(400000u128),
+            vested_amount: Uint128::new(400000u128),
+            claimable_amount: Uint128::new(400000u128),
+        }
+    );
+
+    // the beneficiary claims the vested amount later
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(400000u128, "uusd")],
+        })]
+    );
+
+    let msg = QueryMsg::DenomSummary {
+        denom: Denom::Native("uusd".to_string()),
+    };
+    let res: DenomSummaryResponse = from_binary(&query(deps.as_ref(), env, msg).unwrap()).unwrap();
+    assert_eq!(res.claimed_amount, Uint128::new(400000u128));
+    assert_eq!(res.returned_amount, Uint128::new(600000u128));
+    assert_eq!(res.unclaimed_amount, Uint128::zero());
+    assert_eq!(res.locked_amount, Uint128::zero());
+}
+
+#[test]
+fn claim_with_msg() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let vesting_schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: vesting_schedule.clone(),
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::new(1000000u128),
+        msg: to_binary(&Cw20HookMsg::RegisterVestingAccount {
+            master_address: None,
+            address: "addr0001".to_string(),
+            vesting_schedule,
+        })
+        .unwrap(),
+    });
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("token0001", &[]), msg).unwrap();
+
+    // half claimable
+    env.block.time = Timestamp::from_seconds(105);
+
+    // recipient contract must be given
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: Some(Binary::from(b"{\"stake\":{}}".to_vec())),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::MissingRecipient {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![
+            Denom::Native("uusd".to_string()),
+            Denom::Cw20(Addr::unchecked("token0001")),
+        ],
+        grant_ids: None,
+        recipient: Some("staking0000".to_string()),
+        msg: Some(Binary::from(b"{\"stake\":{}}".to_vec())),
+    };
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(WasmMsg::Execute {
+                contract_addr: "staking0000".to_string(),
+                funds: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::new(500000u128),
+                }],
+                msg: Binary::from(b"{\"stake\":{}}".to_vec()),
+            }),
+            SubMsg::new(WasmMsg::Execute {
+                contract_addr: "token0001".to_string(),
+                funds: vec![],
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: "staking0000".to_string(),
+                    amount: Uint128::new(500000u128),
+                    msg: Binary::from(b"{\"stake\":{}}".to_vec()),
+                })
+                .unwrap(),
+            }),
+        ]
+    );
+}
+
+#[test]
+fn claim_by_operator() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // unauthorized operator
+    env.block.time = Timestamp::from_seconds(102);
+    let claim_msg = ExecuteMsg::ClaimFor {
+        address: "addr0001".to_string(),
+        denoms: vec![Denom::Native("uusd".to_string())],
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("keeper0000", &[]),
+        claim_msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // cannot authorize an invalid operator or recipient
+    for (operator, recipient) in [("a", "wallet0001"), ("keeper0000", "a")] {
+        let msg = ExecuteMsg::AuthorizeOperator {
+            operator: operator.to_string(),
+            recipient: Some(recipient.to_string()),
+            expires: None,
+        };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+        match res.unwrap_err() {
+            ContractError::Std(StdError::GenericErr { .. }) => {}
+            _ => panic!("should not enter"),
+        }
+    }
+
+    let msg = ExecuteMsg::AuthorizeOperator {
+        operator: "keeper0000".to_string(),
+        recipient: Some("wallet0001".to_string()),
+        expires: Some(Expiration::AtTime(Timestamp::from_seconds(105))),
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    assert_eq!(
+        from_binary::<OperatorsResponse>(
+            &query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::Operators {
+                    address: "addr0001".to_string(),
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap()
+        )
+        .unwrap(),
+        OperatorsResponse {
+            operators: vec![OperatorInfo {
+                operator: "keeper0000".to_string(),
+                recipient: Some("wallet0001".to_string()),
+                expires: Expiration::AtTime(Timestamp::from_seconds(105)),
+            }],
+        }
+    );
+
+    // claimed tokens go to the preset recipient
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("keeper0000", &[]),
+        claim_msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "wallet0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::new(200000u128),
+            }],
+        }),]
+    );
+
+    // expired authorization
+    env.block.time = Timestamp::from_seconds(105);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("keeper0000", &[]),
+        claim_msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::Expired {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // revoked authorization
+    let msg = ExecuteMsg::AuthorizeOperator {
+        operator: "keeper0000".to_string(),
+        recipient: None,
+        expires: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    let msg = ExecuteMsg::RevokeOperator {
+        operator: "keeper0000".to_string(),
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    let res = execute(deps.as_mut(), env, mock_info("keeper0000", &[]), claim_msg);
+    match res.unwrap_err() {
+        ContractError::Unauthorized {} => {}
+        _ => panic!("should not enter"),
+    }
+}
+
+#[test]
+fn register_and_claim_by_height() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to height 1000
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    env.block.height = 1000;
+
+    // start height already passed
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::PeriodicVestingByHeight {
+            start_height: Uint64::new(999),
+            end_height: Uint64::new(1099),
+            vesting_interval: Uint64::new(50),
+            amount: Uint128::new(100000u128),
+        },
+    };
+
+    let info = mock_info("addr0000", &[Coin::new(300000u128, "uusd")]);
+    let res = execute(deps.as_mut(), env.clone(), info.clone(), msg);
+    match res.unwrap_err() {
+        ContractError::InvalidSchedule { reason } => {
+            assert_eq!(reason, "assert(start_height >= block_height)")
+        }
+        _ => panic!("should not enter"),
+    }
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::PeriodicVestingByHeight {
+            start_height: Uint64::new(1000),
+            end_height: Uint64::new(1100),
+            vesting_interval: Uint64::new(50),
+            amount: Uint128::new(100000u128),
+        },
+    };
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // only block height matters
+    env.block.time = Timestamp::from_seconds(1000000);
+    env.block.height = 1075;
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::new(200000u128),
+            }],
+        }),]
+    );
+}
+
+#[test]
+fn simulate_vesting() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let schedule = VestingSchedule::LinearVesting {
+        start_time: Uint64::new(100),
+        end_time: Uint64::new(110),
+        vesting_amount: Uint128::new(1000000u128),
+    };
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: schedule.clone(),
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // claim at time 105
+    env.block.time = Timestamp::from_seconds(105);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    // nothing is claimable before the last claim
+    let msg = QueryMsg::SimulateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        at_time: 103,
+        at_height: None,
+    };
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap())
+            .unwrap(),
+        VestingSimulationResponse {
+            vesting_amount: Uint128::new(1000000u128),
+            simulations: vec![VestingSimulation {
+                at_time: 103,
+                vested_amount: Uint128::new(300000u128),
+                claimable_amount: Uint128::zero(),
+            }],
+        }
+    );
+
+    let msg = QueryMsg::SimulateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        at_time: 108,
+        at_height: None,
+    };
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap())
+            .unwrap(),
+        VestingSimulationResponse {
+            vesting_amount: Uint128::new(1000000u128),
+            simulations: vec![VestingSimulation {
+                at_time: 108,
+                vested_amount: Uint128::new(800000u128),
+                claimable_amount: Uint128::new(300000u128),
+            }],
+        }
+    );
+
+    // unknown vesting account
+    let msg = QueryMsg::SimulateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("ukrw".to_string()),
+        grant_id: None,
+        at_time: 108,
+        at_height: None,
+    };
+    match query(deps.as_ref(), env.clone(), msg).unwrap_err() {
+        StdError::GenericErr { msg, .. } => assert_eq!(
+            msg,
+            "Vesting entry is not found for denom {\"native\":\"ukrw\"}"
+        ),
+        _ => panic!("should not enter"),
+    }
+
+    // preview does not require a registered account
+    let msg = QueryMsg::PreviewSchedule {
+        schedule: VestingSchedule::PeriodicVesting {
+            start_time: Uint64::new(200),
+            end_time: Uint64::new(220),
+            vesting_interval: Uint64::new(10),
+            amount: Uint128::new(100000u128),
+        },
+        at_times: vec![199, 200, 215, 220],
+    };
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(&query(deps.as_ref(), env, msg).unwrap()).unwrap(),
+        VestingSimulationResponse {
+            vesting_amount: Uint128::new(300000u128),
+            simulations: vec![
+                VestingSimulation {
+                    at_time: 199,
+                    vested_amount: Uint128::zero(),
+                    claimable_amount: Uint128::zero(),
+                },
+                VestingSimulation {
+                    at_time: 200,
+                    vested_amount: Uint128::new(100000u128),
+                    claimable_amount: Uint128::new(100000u128),
+                },
+                VestingSimulation {
+                    at_time: 215,
+                    vested_amount: Uint128::new(200000u128),
+                    claimable_amount: Uint128::new(200000u128),
+                },
+                VestingSimulation {
+                    at_time: 220,
+                    vested_amount: Uint128::new(300000u128),
+                    claimable_amount: Uint128::new(300000u128),
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn simulate_mixed_vesting() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100 and height 1000
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    env.block.height = 1000;
+
+    let msg = ExecuteMsg::RegisterVestingAccounts {
+        vesting_accounts: vec![
+            VestingAccountMsg {
+                master_address: None,
+                address: "addr0001".to_string(),
+                vesting_schedule: VestingSchedule::LinearVesting {
+                    start_time: Uint64::new(100),
+                    end_time: Uint64::new(110),
+                    vesting_amount: Uint128::new(1000000u128),
+                },
+            },
+            VestingAccountMsg {
+                master_address: None,
+                address: "addr0001".to_string(),
+                vesting_schedule: VestingSchedule::LinearVestingByHeight {
+                    start_height: Uint64::new(1000),
+                    end_height: Uint64::new(1100),
+                    vesting_amount: Uint128::new(1000000u128),
+                },
+            },
+        ],
+    };
+    let info = mock_info("addr0000", &[Coin::new(2000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // the time based grant is evaluated at at_time and the height based one at at_height
+    let msg = QueryMsg::SimulateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        at_time: 105,
+        at_height: Some(1025),
+    };
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap())
+            .unwrap(),
+        VestingSimulationResponse {
+            vesting_amount: Uint128::new(2000000u128),
+            simulations: vec![VestingSimulation {
+                at_time: 105,
+                vested_amount: Uint128::new(750000u128),
+                claimable_amount: Uint128::new(750000u128),
+            }],
+        }
+    );
+
+    // at_height defaults to the current block height
+    env.block.height = 1050;
+    let msg = QueryMsg::SimulateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        at_time: 110,
+        at_height: None,
+    };
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(&query(deps.as_ref(), env, msg).unwrap()).unwrap(),
+        VestingSimulationResponse {
+            vesting_amount: Uint128::new(2000000u128),
+            simulations: vec![VestingSimulation {
+                at_time: 110,
+                vested_amount: Uint128::new(1500000u128),
+                claimable_amount: Uint128::new(1500000u128),
+            }],
+        }
+    );
+}
+
+#[test]
+fn query_voting_power() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100 and height 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+    env.block.height = 100;
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: None,
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(110),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uusd")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    // claim the vested half
+    env.block.time = Timestamp::from_seconds(105);
+    env.block.height = 101;
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    fn voting_power(deps: Deps, env: Env, height: Option<u64>) -> Uint128 {
+        let msg = QueryMsg::VotingPower {
+            address: "addr0001".to_string(),
+            denom: Denom::Native("uusd".to_string()),
+            height,
+        };
+        from_binary::<VotingPowerResponse>(&query(deps, env, msg).unwrap())
+            .unwrap()
+            .voting_power
+    }
+
+    // snapshots are taken at the beginning of the block
+    assert_eq!(
+        voting_power(deps.as_ref(), env.clone(), Some(100)),
+        Uint128::zero()
+    );
+    assert_eq!(
+        voting_power(deps.as_ref(), env.clone(), Some(101)),
+        Uint128::new(1000000u128)
+    );
+    assert_eq!(
+        voting_power(deps.as_ref(), env.clone(), Some(102)),
+        Uint128::new(500000u128)
+    );
+    assert_eq!(
+        voting_power(deps.as_ref(), env.clone(), None),
+        Uint128::new(500000u128)
+    );
+
+    // fully claimed account has no voting power
+    env.block.time = Timestamp::from_seconds(110);
+    env.block.height = 102;
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    assert_eq!(
+        voting_power(deps.as_ref(), env.clone(), Some(102)),
+        Uint128::new(500000u128)
+    );
+    assert_eq!(
+        voting_power(deps.as_ref(), env.clone(), None),
+        Uint128::zero()
+    );
+}
+
+#[test]
+fn delegate_and_undelegate() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.update_staking("uluna", &[], &[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: Some(100),
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    // init env to time 100
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    for (address, master_address) in [("addr0001", Some("master0000")), ("addr0002", None)] {
+        let msg = ExecuteMsg::RegisterVestingAccount {
+            master_address: master_address.map(|v| v.to_string()),
+            address: address.to_string(),
+            vesting_schedule: VestingSchedule::LinearVesting {
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(200),
+                vesting_amount: Uint128::new(1000000u128),
+            },
+        };
+        let info = mock_info("addr0000", &[Coin::new(1000000u128, "uluna")]);
+        let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    }
+
+    // only the bonded denom can be delegated
+    let msg = ExecuteMsg::Delegate {
+        denom: Denom::Native("ukrw".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(600000u128),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::NotStakable {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    let msg = ExecuteMsg::Delegate {
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(1000001u128),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::InsufficientFunds { available } => {
+            assert_eq!(available, Uint128::new(1000000u128))
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // first delegation to the validator has no rewards to withdraw
+    let msg = ExecuteMsg::Delegate {
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(600000u128),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(StakingMsg::Delegate {
+            validator: "val0000".to_string(),
+            amount: Coin::new(600000u128, "uluna"),
+        })]
+    );
+    set_delegation(&mut deps.querier, "val0000", 600000u128);
+
+    // only the unstaked part of the vested amount is claimed
+    env.block.time = Timestamp::from_seconds(150);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uluna".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(400000u128, "uluna")],
+        })]
+    );
+
+    // rewards are withdrawn before the delegation changes
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(1000000u128, "uluna")]);
+    let msg = ExecuteMsg::Delegate {
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(200000u128),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0002", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::reply_on_success(
+            DistributionMsg::WithdrawDelegatorReward {
+                validator: "val0000".to_string(),
+            },
+            WITHDRAW_REWARDS_REPLY_ID,
+        )]
+    );
+
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(1006000u128, "uluna"), Coin::new(3000u128, "uusd")],
+    );
+    let res = reply(deps.as_mut(), env.clone(), withdraw_rewards_reply()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(StakingMsg::Delegate {
+            validator: "val0000".to_string(),
+            amount: Coin::new(200000u128, "uluna"),
+        })]
+    );
+    set_delegation(&mut deps.querier, "val0000", 800000u128);
+
+    // rewards are shared by the delegated amounts
+    let msg = ExecuteMsg::WithdrawRewards {
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    deps.querier.update_balance(
+        MOCK_CONTRACT_ADDR,
+        vec![Coin::new(1022000u128, "uluna"), Coin::new(3000u128, "uusd")],
+    );
+    let res = reply(deps.as_mut(), env.clone(), withdraw_rewards_reply()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(18000u128, "uluna"), Coin::new(3000u128, "uusd")],
+        })]
+    );
+
+    // staked funds block the deregistration
+    let msg = ExecuteMsg::DeregisterVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        vested_token_recipient: None,
+        left_vesting_token_recipient: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg,
+    );
+    match res.unwrap_err() {
+        ContractError::StakedFunds {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // master can undelegate
+    let msg = ExecuteMsg::Undelegate {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(600000u128),
+    };
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg,
+    )
+    .unwrap();
+    let res = reply(deps.as_mut(), env.clone(), withdraw_rewards_reply()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(StakingMsg::Undelegate {
+            validator: "val0000".to_string(),
+            amount: Coin::new(600000u128, "uluna"),
+        })]
+    );
+    set_delegation(&mut deps.querier, "val0000", 200000u128);
+
+    let msg = QueryMsg::Stake {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+    };
+    assert_eq!(
+        from_binary::<StakeResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap(),
+        StakeResponse {
+            delegated_amount: Uint128::zero(),
+            delegations: vec![],
+            unbondings: vec![UnbondingInfo {
+                amount: Uint128::new(600000u128),
+                release_time: Uint64::new(250),
+            }],
+        }
+    );
+
+    let msg = QueryMsg::Stake {
+        address: "addr0002".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+    };
+    assert_eq!(
+        from_binary::<StakeResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap(),
+        StakeResponse {
+            delegated_amount: Uint128::new(200000u128),
+            delegations: vec![DelegationInfo {
+                validator: "val0000".to_string(),
+                amount: Uint128::new(200000u128),
+            }],
+            unbondings: vec![],
+        }
+    );
+
+    // the simulation excludes the unbonding tokens until the release
+    let msg_simulate = |at_time: u64| QueryMsg::SimulateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        at_time,
+        at_height: None,
+    };
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(
+            &query(deps.as_ref(), env.clone(), msg_simulate(200)).unwrap()
+        )
+        .unwrap()
+        .simulations,
+        vec![VestingSimulation {
+            at_time: 200,
+            vested_amount: Uint128::new(1000000u128),
+            claimable_amount: Uint128::zero(),
+        }]
+    );
+    assert_eq!(
+        from_binary::<VestingSimulationResponse>(
+            &query(deps.as_ref(), env.clone(), msg_simulate(250)).unwrap()
+        )
+        .unwrap()
+        .simulations,
+        vec![VestingSimulation {
+            at_time: 250,
+            vested_amount: Uint128::new(1000000u128),
+            claimable_amount: Uint128::new(600000u128),
+        }]
+    );
+
+    // unbonding tokens are claimable after the release
+    env.block.time = Timestamp::from_seconds(200);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uluna".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![]);
+
+    // the released tokens are paid only when they are returned to the contract in full,
+    // a slashed unbonding can't be paid out of the deposit of addr0002
+    env.block.time = Timestamp::from_seconds(250);
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(1100000u128, "uluna")]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg.clone(),
+    );
+    match res.unwrap_err() {
+        ContractError::InsufficientFunds { available } => {
+            assert_eq!(available, Uint128::new(300000u128))
+        }
+        _ => panic!("should not enter"),
+    }
+
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(1400000u128, "uluna")]);
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(600000u128, "uluna")],
+        })]
+    );
+}
+
+#[test]
+fn slashed_delegation() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.update_staking("uluna", &[], &[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: Some(100),
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: Some("master0000".to_string()),
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(200),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uluna")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Delegate {
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(600000u128),
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+
+    // the validator is slashed by 10%
+    set_delegation(&mut deps.querier, "val0000", 540000u128);
+
+    let msg = QueryMsg::Stake {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+    };
+    assert_eq!(
+        from_binary::<StakeResponse>(&query(deps.as_ref(), env.clone(), msg.clone()).unwrap())
+            .unwrap(),
+        StakeResponse {
+            delegated_amount: Uint128::new(540000u128),
+            delegations: vec![DelegationInfo {
+                validator: "val0000".to_string(),
+                amount: Uint128::new(540000u128),
+            }],
+            unbondings: vec![],
+        }
+    );
+
+    // only the real delegation can be undelegated
+    let msg_undelegate = |amount: u128| ExecuteMsg::Undelegate {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(amount),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg_undelegate(600000u128),
+    );
+    match res.unwrap_err() {
+        ContractError::InsufficientFunds { available } => {
+            assert_eq!(available, Uint128::new(540000u128))
+        }
+        _ => panic!("should not enter"),
+    }
+
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg_undelegate(540000u128),
+    )
+    .unwrap();
+    let res = reply(deps.as_mut(), env.clone(), withdraw_rewards_reply()).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(StakingMsg::Undelegate {
+            validator: "val0000".to_string(),
+            amount: Coin::new(540000u128, "uluna"),
+        })]
+    );
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("slashed_amount", "60000")));
+    set_delegation(&mut deps.querier, "val0000", 0u128);
+
+    assert_eq!(
+        from_binary::<StakeResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap(),
+        StakeResponse {
+            delegated_amount: Uint128::zero(),
+            delegations: vec![],
+            unbondings: vec![UnbondingInfo {
+                amount: Uint128::new(540000u128),
+                release_time: Uint64::new(200),
+            }],
+        }
+    );
+
+    // the slashed amount is lost from the grant
+    let msg = QueryMsg::DenomSummary {
+        denom: Denom::Native("uluna".to_string()),
+    };
+    let res: DenomSummaryResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), msg).unwrap()).unwrap();
+    assert_eq!(res.slashed_amount, Uint128::new(60000u128));
+
+    // the slashed grant can still be extended without a deposit
+    let msg_amend = |end_time: u64| ExecuteMsg::AmendVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        end_time: Some(Uint64::new(end_time)),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg_amend(300),
+    )
+    .unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("vesting_amount", "940000")));
+
+    // and topped up with the lost amount
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[Coin::new(60000u128, "uluna")]),
+        msg_amend(300),
+    )
+    .unwrap();
+    assert!(res
+        .attributes
+        .contains(&Attribute::new("vesting_amount", "1000000")));
+
+    env.block.time = Timestamp::from_seconds(300);
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(1000000u128, "uluna")]);
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uluna".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(1000000u128, "uluna")],
+        })]
+    );
+}
+
+#[test]
+fn terminate_staked_vesting() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.update_staking("uluna", &[], &[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: Some(100),
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    let msg = ExecuteMsg::RegisterVestingAccount {
+        master_address: Some("master0000".to_string()),
+        address: "addr0001".to_string(),
+        vesting_schedule: VestingSchedule::LinearVesting {
+            start_time: Uint64::new(100),
+            end_time: Uint64::new(200),
+            vesting_amount: Uint128::new(1000000u128),
+        },
+    };
+    let info = mock_info("addr0000", &[Coin::new(1000000u128, "uluna")]);
+    let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+
+    let msg = ExecuteMsg::Delegate {
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(800000u128),
+    };
+    let _ = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    set_delegation(&mut deps.querier, "val0000", 800000u128);
+
+    // the vesting stops at once, the unstaked part of the unvested tokens is returned
+    env.block.time = Timestamp::from_seconds(130);
+    let msg = ExecuteMsg::TerminateVesting {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        left_vesting_token_recipient: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "master0000".to_string(),
+            amount: vec![Coin::new(200000u128, "uluna")],
+        })]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "terminate_vesting"),
+            ("address", "addr0001"),
+            ("grant_id", "1"),
+            ("vesting_denom", "{\"native\":\"uluna\"}"),
+            ("vested_amount", "300000"),
+            ("left_vesting_amount", "200000"),
+            ("pending_return_amount", "500000"),
+        ]
+    );
+
+    // the staked tokens are not claimable while the return is pending
+    env.block.time = Timestamp::from_seconds(150);
+    let msg_claim = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uluna".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("addr0001", &[]),
+        msg_claim.clone(),
+    )
+    .unwrap();
+    assert_eq!(res.messages, vec![]);
+
+    let msg_undelegate = ExecuteMsg::Undelegate {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uluna".to_string()),
+        grant_id: None,
+        validator: "val0000".to_string(),
+        amount: Uint128::new(800000u128),
+    };
+    let _ = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg_undelegate,
+    )
+    .unwrap();
+    let _ = reply(deps.as_mut(), env.clone(), withdraw_rewards_reply()).unwrap();
+    set_delegation(&mut deps.querier, "val0000", 0u128);
+
+    // the rest of the return is settled once released
+    env.block.time = Timestamp::from_seconds(250);
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(800000u128, "uluna")]);
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "master0000".to_string(),
+            amount: vec![Coin::new(500000u128, "uluna")],
+        })]
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("master0000", &[]),
+        msg,
+    );
+    match res.unwrap_err() {
+        ContractError::AlreadyTerminated {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    deps.querier
+        .update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(300000u128, "uluna")]);
+    let res = execute(deps.as_mut(), env, mock_info("addr0001", &[]), msg_claim).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(300000u128, "uluna")],
+        })]
+    );
+}
+
+#[test]
+fn multiple_grants() {
+    let mut deps = mock_dependencies(&[]);
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        InstantiateMsg {
+            unbonding_period: None,
+            admin: None,
+            allowed_denoms: None,
+        },
+    )
+    .unwrap();
+
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(100);
+
+    // initial grant and a refresher grant of the same denom
+    for (end_time, amount) in [(110u64, 1000000u128), (120u64, 2000000u128)] {
+        let msg = ExecuteMsg::RegisterVestingAccount {
+            master_address: Some("addr0000".to_string()),
+            address: "addr0001".to_string(),
+            vesting_schedule: VestingSchedule::LinearVesting {
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(end_time),
+                vesting_amount: Uint128::new(amount),
+            },
+        };
+        let info = mock_info("addr0000", &[Coin::new(amount, "uusd")]);
+        let _ = execute(deps.as_mut(), env.clone(), info, msg).unwrap();
+    }
+
+    env.block.time = Timestamp::from_seconds(105);
+    let msg = QueryMsg::VestingSummary {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+    };
+    assert_eq!(
+        from_binary::<VestingSummaryResponse>(&query(deps.as_ref(), env.clone(), msg).unwrap())
+            .unwrap(),
+        VestingSummaryResponse {
+            address: "addr0001".to_string(),
+            vesting_denom: Denom::Native("uusd".to_string()),
+            grant_ids: vec![1, 2],
+            vesting_amount: Uint128::new(3000000u128),
+            vested_amount: Uint128::new(1000000u128),
+            claimable_amount: Uint128::new(1000000u128),
+        }
+    );
+
+    // claim only the refresher grant
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: Some(vec![2]),
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(500000u128, "uusd")],
+        })]
+    );
+
+    // claim the rest of all grants
+    let msg = ExecuteMsg::Claim {
+        denoms: vec![Denom::Native("uusd".to_string())],
+        grant_ids: None,
+        recipient: None,
+        msg: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0001", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin::new(500000u128, "uusd")],
+        })]
+    );
+
+    // single grant operations require the grant_id
+    let msg = ExecuteMsg::AmendVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: None,
+        end_time: Some(Uint64::new(130)),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    match res.unwrap_err() {
+        ContractError::GrantIdRequired {} => {}
+        _ => panic!("should not enter"),
+    }
+
+    // deregister the refresher grant only
+    env.block.time = Timestamp::from_seconds(110);
+    let msg = ExecuteMsg::DeregisterVestingAccount {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        grant_id: Some(2),
+        vested_token_recipient: None,
+        left_vesting_token_recipient: None,
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(BankMsg::Send {
+                to_address: "addr0001".to_string(),
+                amount: vec![Coin::new(500000u128, "uusd")],
+            }),
+            SubMsg::new(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![Coin::new(1000000u128, "uusd")],
+            }),
+        ]
+    );
+
+    // the voting power is summed over the remaining grants
+    let msg = QueryMsg::VotingPower {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        height: None,
+    };
+    assert_eq!(
+        from_binary::<VotingPowerResponse>(&query(deps.as_ref(), env, msg).unwrap())
+            .unwrap()
+            .voting_power,
+        Uint128::new(500000u128)
+    );
+}
+
+fn withdraw_rewards_reply() -> Reply {
+    Reply {
+        id: WITHDRAW_REWARDS_REPLY_ID,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    }
+}
+
+/// real delegation of the contract to the validator
+fn set_delegation(querier: &mut MockQuerier, validator: &str, amount: u128) {
+    querier.update_staking(
+        "uluna",
+        &[],
+        &[FullDelegation {
+            delegator: Addr::unchecked(MOCK_CONTRACT_ADDR),
+            validator: validator.to_string(),
+            amount: Coin::new(amount, "uluna"),
+            can_redelegate: Coin::new(amount, "uluna"),
+            accumulated_rewards: vec![],
+        }],
+    );
+}
+
+/// save a vesting account as the unversioned contract stored it,
+/// returns the storage key of the account
+fn save_legacy_account(
+    storage: &mut dyn Storage,
+    address: &str,
+    denom: &str,
+    master_address: Option<&str>,
+    claimed_amount: u128,
+) -> Vec<u8> {
+    // the length prefixed namespace and address followed by the denom key
+    let mut key: Vec<u8> = vec![];
+    for part in [b"vesting_accounts".as_ref(), address.as_bytes()] {
+        key.extend_from_slice(&(part.len() as u16).to_be_bytes());
+        key.extend_from_slice(part);
+    }
+    key.extend_from_slice(format!("native-{}", denom).as_bytes());
+
+    let master_address = master_address.map_or("null".to_string(), |m| format!("\"{}\"", m));
+    let value = format!(
+        "{{\"master_address\":{},\"address\":\"{}\",\"vesting_denom\":{{\"native\":\"{}\"}},\
+         \"vesting_amount\":\"1000000\",\"vesting_schedule\":{{\"linear_vesting\":\
+         {{\"start_time\":\"100\",\"end_time\":\"110\",\"vesting_amount\":\"1000000\"}}}},\
+         \"claimed_amount\":\"{}\"}}",
+        master_address, address, denom, claimed_amount
+    );
+    storage.set(&key, value.as_bytes());
+    key
+}
+
+#[test]
+fn migrate_legacy_contract() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    // records of the unversioned contract were stored without indexes
+    let legacy_key = save_legacy_account(
+        deps.as_mut().storage,
+        "addr0001",
+        "uusd",
+        Some("master0000"),
+        300000,
+    );
+
+    let query_by_denom = QueryMsg::VestingAccountsByDenom {
+        denom: Denom::Native("uusd".to_string()),
+        start_after: None,
+        limit: None,
+    };
+    let res: VestingAccountsResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), query_by_denom.clone()).unwrap()).unwrap();
+    assert_eq!(res.vesting_accounts, vec![]);
+
+    let res = migrate(
+        deps.as_mut(),
+        env.clone(),
+        MigrateMsg {
+            admin: None,
+            limit: None,
+        },
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "migrate"),
+            ("previous_version", "0.0.0"),
+            ("version", env!("CARGO_PKG_VERSION")),
+            ("migrated_accounts", "1"),
+            ("completed", "true"),
+        ]
+    );
+    assert_eq!(
+        get_contract_version(deps.as_ref().storage).unwrap(),
+        ContractVersion {
+            contract: "crates.io:token-vesting".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    );
+
+    let res: VestingAccountsResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), query_by_denom).unwrap()).unwrap();
+    assert_eq!(res.vesting_accounts.len(), 1);
+    assert_eq!(res.vesting_accounts[0].address, "addr0001");
+    assert_eq!(res.vesting_accounts[0].vesting.grant_id, 1);
+    assert_eq!(
+        vesting_accounts()
+            .load(deps.as_ref().storage, U64Key::new(1))
+            .unwrap(),
+        VestingAccount {
+            grant_id: 1,
+            master_address: Some("master0000".to_string()),
+            address: "addr0001".to_string(),
+            vesting_denom: Denom::Native("uusd".to_string()),
+            vesting_amount: Uint128::new(1000000u128),
+            vesting_schedule: VestingSchedule::LinearVesting {
+                start_time: Uint64::new(100),
+                end_time: Uint64::new(110),
+                vesting_amount: Uint128::new(1000000u128),
+            },
+            claimed_amount: Uint128::new(300000u128),
+            terminated_at: None,
+            pending_return_amount: Uint128::zero(),
+            return_address: None,
+        }
+    );
+    assert_eq!(deps.as_ref().storage.get(&legacy_key), None);
+    let voting_power_msg = QueryMsg::VotingPower {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        height: None,
+    };
+    assert_eq!(
+        from_binary::<VotingPowerResponse>(
+            &query(deps.as_ref(), env.clone(), voting_power_msg).unwrap()
+        )
+        .unwrap()
+        .voting_power,
+        Uint128::new(700000u128)
+    );
+    let res: DenomSummaryResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::DenomSummary {
+                denom: Denom::Native("uusd".to_string()),
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(res.unclaimed_amount, Uint128::new(700000u128));
+
+    // refuse downgrades
+    set_contract_version(deps.as_mut().storage, "crates.io:token-vesting", "99.0.0").unwrap();
+    let res = migrate(
+        deps.as_mut(),
+        env.clone(),
+        MigrateMsg {
+            admin: None,
+            limit: None,
+        },
+    );
+    match res.unwrap_err() {
+        ContractError::CannotDowngrade { previous_version } => {
+            assert_eq!(previous_version, "99.0.0")
+        }
+        _ => panic!("should not enter"),
+    }
+
+    // refuse other contracts
+    set_contract_version(deps.as_mut().storage, "crates.io:cw20-base", "0.1.0").unwrap();
+    let res = migrate(
+        deps.as_mut(),
+        env,
+        MigrateMsg {
+            admin: None,
+            limit: None,
+        },
+    );
+    match res.unwrap_err() {
+        ContractError::CannotMigrate { previous_contract } => {
+            assert_eq!(previous_contract, "crates.io:cw20-base")
+        }
+        _ => panic!("should not enter"),
+    }
+}
+
+#[test]
+fn migrate_in_batches() {
+    let mut deps = mock_dependencies(&[]);
+    let env = mock_env();
+
+    for (address, denom) in [
+        ("addr0001", "uusd"),
+        ("addr0001", "ukrw"),
+        ("addr0002", "uusd"),
+    ] {
+        save_legacy_account(deps.as_mut().storage, address, denom, None, 0);
+    }
+
+    // the admin must be a valid address
+    let res = migrate(
+        deps.as_mut(),
+        env.clone(),
+        MigrateMsg {
+            admin: Some("a".to_string()),
+            limit: Some(2),
+        },
+    );
+    match res.unwrap_err() {
+        ContractError::Std(StdError::GenericErr { .. }) => {}
+        _ => panic!("should not enter"),
+    }
+
+    let msg = MigrateMsg {
+        admin: Some("admin0000".to_string()),
+        limit: Some(2),
+    };
+    let res = migrate(deps.as_mut(), env.clone(), msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            ("action", "migrate"),
+            ("previous_version", "0.0.0"),
+            ("version", env!("CARGO_PKG_VERSION")),
+            ("migrated_accounts", "0"),
+            ("completed", "false"),
+        ]
+    );
+
+    // the contract is not usable until the migration completes
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("admin0000", &[]),
+        ExecuteMsg::Pause {},
+    );
+    match res.unwrap_err() {
+        ContractError::MigrationInProgress {} => {}
+        _ => panic!("should not enter"),
+    }
+    let voting_power_msg = QueryMsg::VotingPower {
+        address: "addr0001".to_string(),
+        denom: Denom::Native("uusd".to_string()),
+        height: None,
+    };
+    assert!(query(deps.as_ref(), env.clone(), voting_power_msg.clone()).is_err());
+    assert_eq!(get_contract_version(deps.as_ref().storage).ok(), None);
+
+    // resume until completed
+    let mut calls = 1;
+    loop {
+        calls += 1;
+        let res = migrate(deps.as_mut(), env.clone(), msg.clone()).unwrap();
+        if res
+            .attributes
+            .contains(&Attribute::new("completed", "true"))
+        {
+            assert!(res
+                .attributes
+                .contains(&Attribute::new("migrated_accounts", "3")));
+            break;
+        }
+    }
+    assert_eq!(calls, 5);
+
+    let res: ConfigResponse =
+        from_binary(&query(deps.as_ref(), env.clone(), QueryMsg::Config {}).unwrap()).unwrap();
+    assert_eq!(res.admin, Some("admin0000".to_string()));
+
+    let res: VestingAccountsResponse = from_binary(
+        &query(
+            deps.as_ref(),
+            env.clone(),
+            QueryMsg::VestingAccounts {
+                start_after: None,
+                limit: None,
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        res.vesting_accounts
+            .iter()
+            .map(|v| v.vesting.grant_id)
+            .collect::<Vec<u64>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(
+        from_binary::<VotingPowerResponse>(&query(deps.as_ref(), env, voting_power_msg).unwrap())
+            .unwrap()
+            .voting_power,
+        Uint128::new(1000000u128)
+    );
+    assert_eq!(
+        get_contract_version(deps.as_ref().storage).unwrap().version,
+        env!("CARGO_PKG_VERSION")
+    );
 }